@@ -0,0 +1,226 @@
+use crate::ast_parser::*;
+use crate::control_flow_analysis::ControlFlowAnalysis;
+use crate::cubic_solver::CubicSolver;
+use crate::declaration_analysis::DeclarationAnalysis;
+use crate::dfs::Dfs;
+use crate::points_to::{locations, Location, PointsTo};
+use std::collections::{HashMap, HashSet};
+
+/// constraint variable of the inclusion-based analysis
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+enum Node {
+    /// content of an abstract location
+    Cell(Location),
+    /// value of an expression
+    Expr(AstNode),
+    /// returned value of an AstNode::Function
+    Ret(AstNode),
+}
+
+/// generate the subset constraints of a program, see the TIP book
+/// the analysis is flow-insensitive and field-insensitive:
+/// a record points to whatever any of its fields points to
+struct AndersenConstraints {
+    solver: CubicSolver<Location, Node>,
+    // generate from DeclarationAnalysis
+    decl: HashMap<AstNode, AstNode>,
+    locations: Vec<Location>,
+    /// resolve the calls through function values
+    cfa: ControlFlowAnalysis,
+}
+
+impl AndersenConstraints {
+    fn node(&self, node: &AstNode) -> Node {
+        match self.decl.get(node) {
+            Some(x) => Node::Cell(Location::Var(x.clone())),
+            None => Node::Expr(node.clone()),
+        }
+    }
+
+    /// ⟦*pointer⟧ ⊆ ⟦to⟧
+    fn load(&mut self, pointer: &AstNode, to: &Node) {
+        let p = self.node(pointer);
+        for c in &self.locations {
+            self.solver
+                .add_conditional(c, &p, &Node::Cell(c.clone()), to);
+        }
+    }
+
+    /// ⟦from⟧ ⊆ ⟦*pointer⟧
+    fn store(&mut self, pointer: &AstNode, from: &Node) {
+        let p = self.node(pointer);
+        for c in &self.locations {
+            self.solver
+                .add_conditional(c, &p, from, &Node::Cell(c.clone()));
+        }
+    }
+}
+
+impl Dfs for AndersenConstraints {
+    type ResultType = Andersen;
+
+    fn new(node: &AstNode) -> Self {
+        Self {
+            solver: CubicSolver::new(),
+            decl: DeclarationAnalysis::work(node),
+            locations: locations(node),
+            cfa: ControlFlowAnalysis::new(node),
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        match node.kind {
+            // &x
+            AstNodeKind::Ref(Ref { ref id }) => {
+                if let Some(x) = self.decl.get(id) {
+                    self.solver
+                        .add_constant(&Location::Var(x.clone()), &self.node(node));
+                }
+            }
+            // alloc e
+            AstNodeKind::Alloc(Alloc { ref expr }) => {
+                let cell = Location::Alloc(node.clone());
+                self.solver.add_constant(&cell, &self.node(node));
+                self.solver.add_subset(&self.node(expr), &Node::Cell(cell));
+            }
+            // *e
+            AstNodeKind::Deref(Deref { ref atom }) => {
+                self.load(atom, &self.node(node));
+            }
+            AstNodeKind::Assign(Assign {
+                ref left,
+                ref right,
+            }) => {
+                let right = self.node(right);
+                match left.kind {
+                    AstNodeKind::Id(_) => self.solver.add_subset(&right, &self.node(left)),
+                    AstNodeKind::DirectFieldWrite(DirectFieldWrite { ref id, .. }) => {
+                        self.solver.add_subset(&right, &self.node(id))
+                    }
+                    AstNodeKind::DerefWrite(DerefWrite { ref expr }) => self.store(expr, &right),
+                    AstNodeKind::IndirectFieldWrite(IndirectFieldWrite { ref expr, .. }) => {
+                        match expr.kind {
+                            AstNodeKind::Deref(Deref { ref atom }) => self.store(atom, &right),
+                            _ => self.solver.add_subset(&right, &self.node(expr)),
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            AstNodeKind::Record(ref fields) => {
+                for field in fields {
+                    self.solver
+                        .add_subset(&self.node(&field.expression), &self.node(node));
+                }
+            }
+            AstNodeKind::FieldAccess(FieldAccess { ref name, .. }) => {
+                self.solver.add_subset(&self.node(name), &self.node(node));
+            }
+            AstNodeKind::FunApp(ref call) => {
+                for f in self.cfa.callees(node) {
+                    if let AstNodeKind::Function(Function {
+                        params: ref formals,
+                        ..
+                    }) = f.kind
+                    {
+                        for (arg, param) in call.params.iter().zip(formals) {
+                            self.solver.add_subset(
+                                &self.node(arg),
+                                &Node::Cell(Location::Var(param.clone())),
+                            );
+                        }
+                    }
+                    self.solver.add_subset(&Node::Ret(f), &self.node(node));
+                }
+            }
+            AstNodeKind::Function(Function { ref ret, .. }) => {
+                self.solver
+                    .add_subset(&self.node(ret), &Node::Ret(node.clone()));
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        let mut cells = HashMap::new();
+        let mut expressions = HashMap::new();
+        for (k, v) in self.solver.solution() {
+            match k {
+                Node::Cell(c) => {
+                    cells.insert(c, v);
+                }
+                Node::Expr(e) => {
+                    expressions.insert(e, v);
+                }
+                Node::Ret(_) => {}
+            }
+        }
+        Andersen {
+            cells,
+            expressions,
+            decl: self.decl,
+        }
+    }
+}
+
+/// inclusion-based pointer analysis
+pub struct Andersen {
+    /// location => locations its content may point to
+    cells: HashMap<Location, HashSet<Location>>,
+    /// expression => locations its value may point to
+    expressions: HashMap<AstNode, HashSet<Location>>,
+    // generate from DeclarationAnalysis
+    decl: HashMap<AstNode, AstNode>,
+}
+
+impl Andersen {
+    pub fn new(program: &AstNode) -> Self {
+        AndersenConstraints::work(program)
+    }
+
+    /// locations the value of any expression of the program may point to
+    pub fn expression(&self, expr: &AstNode) -> HashSet<Location> {
+        match self.decl.get(expr) {
+            Some(x) => self.points_to(x),
+            None => self.expressions.get(expr).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+impl PointsTo for Andersen {
+    fn targets(&self, loc: &Location) -> HashSet<Location> {
+        self.cells.get(loc).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::andersen::Andersen;
+    use crate::ast_parser::*;
+    use crate::points_to::{names, var, PointsTo};
+
+    #[test]
+    fn test_andersen() {
+        let program = parse(
+            "main() { var n, p, i, x; n = null; i = 3;
+             while (i > 0) { p = alloc null; *p = n; n = p; i = i - 1; }
+             x = &i; return 0; }",
+        );
+        let andersen = Andersen::new(&program);
+        assert_eq!(
+            names(andersen.points_to(&var(&program, "n"))),
+            vec!["alloc-2:34"]
+        );
+        assert_eq!(names(andersen.points_to(&var(&program, "x"))), vec!["i"]);
+        // every node of the list points to the next one
+        let cell = andersen
+            .points_to(&var(&program, "p"))
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(names(andersen.targets(&cell)), vec!["alloc-2:34"]);
+        assert!(andersen.may_alias(&var(&program, "n"), &var(&program, "p")));
+        assert!(!andersen.may_alias(&var(&program, "n"), &var(&program, "x")));
+    }
+}
@@ -1,6 +1,10 @@
+use crate::term::Term;
+use crate::type_parser::TypeBuilder;
+use crate::type_printer::TypePrinter;
 use pest::iterators::{Pair, Pairs};
 use pest::prec_climber::*;
 use pest::Parser;
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Write;
 
@@ -19,7 +23,7 @@ lazy_static! {
 
 #[derive(Parser)]
 #[grammar = "tip.pest"]
-struct IdentParser;
+pub(crate) struct IdentParser;
 
 pub fn parse(input: &str) -> AstNode {
     let pair = IdentParser::parse(Rule::program, input)
@@ -102,6 +106,11 @@ pub struct Function {
     pub statements: Vec<AstNode>,
     /// a expression
     pub ret: Box<AstNode>,
+    /// declared types of params and vars, `x: τ`, Vec<(AstNode::Id, Term)>
+    /// the type variables are shared by all annotations of the function
+    pub annotations: Vec<(AstNode, Term)>,
+    /// declared type of the returned value, `f(..): τ`
+    pub ret_type: Option<Box<Term>>,
 }
 
 impl fmt::Debug for Function {
@@ -195,6 +204,283 @@ impl fmt::Debug for AstNode {
     }
 }
 
+impl AstNode {
+    /// clone an expression with every position set to 0:0
+    /// so that two occurrences of `a+b` are equal
+    pub fn without_position(&self) -> AstNode {
+        let strip = |node: &AstNode| Box::new(node.without_position());
+        let kind = match self.kind {
+            AstNodeKind::Id(_)
+            | AstNodeKind::Number(_)
+            | AstNodeKind::Input
+            | AstNodeKind::Null => self.kind.clone(),
+            AstNodeKind::Record(ref fields) => AstNodeKind::Record(
+                fields
+                    .iter()
+                    .map(|f| Field {
+                        name: f.name.clone(),
+                        expression: strip(&f.expression),
+                    })
+                    .collect(),
+            ),
+            AstNodeKind::Alloc(Alloc { ref expr }) => {
+                AstNodeKind::Alloc(Alloc { expr: strip(expr) })
+            }
+            AstNodeKind::Ref(Ref { ref id }) => AstNodeKind::Ref(Ref { id: strip(id) }),
+            AstNodeKind::Deref(Deref { ref atom }) => {
+                AstNodeKind::Deref(Deref { atom: strip(atom) })
+            }
+            AstNodeKind::FunApp(FunApp {
+                ref method,
+                ref params,
+            }) => AstNodeKind::FunApp(FunApp {
+                method: strip(method),
+                params: params.iter().map(|p| p.without_position()).collect(),
+            }),
+            AstNodeKind::FieldAccess(FieldAccess { ref name, ref path }) => {
+                AstNodeKind::FieldAccess(FieldAccess {
+                    name: strip(name),
+                    path: path.clone(),
+                })
+            }
+            AstNodeKind::Expression(BinaryOp {
+                ref op,
+                ref left,
+                ref right,
+            }) => AstNodeKind::Expression(BinaryOp {
+                op: op.clone(),
+                left: strip(left),
+                right: strip(right),
+            }),
+            _ => unreachable!(),
+        };
+        AstNode {
+            kind,
+            line: 0,
+            col: 0,
+        }
+    }
+
+    /// print TIP source, statements start at `indent` levels
+    fn fmt_indent(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let pad = "    ".repeat(indent);
+        match self.kind {
+            AstNodeKind::Id(ref name) => f.write_str(name),
+            AstNodeKind::DirectFieldWrite(DirectFieldWrite { ref id, ref field }) => {
+                f.write_fmt(format_args!("{}.{}", id, field))
+            }
+            AstNodeKind::IndirectFieldWrite(IndirectFieldWrite {
+                ref expr,
+                ref field,
+            }) => f.write_fmt(format_args!("({}).{}", expr, field)),
+            AstNodeKind::DerefWrite(DerefWrite { ref expr }) => {
+                f.write_fmt(format_args!("*{}", expr))
+            }
+            AstNodeKind::Output(Output { ref expr }) => {
+                f.write_fmt(format_args!("{}output {};\n", pad, expr))
+            }
+            AstNodeKind::Error(Error { ref expr }) => {
+                f.write_fmt(format_args!("{}error {};\n", pad, expr))
+            }
+            AstNodeKind::Assign(Assign {
+                ref left,
+                ref right,
+            }) => f.write_fmt(format_args!("{}{} = {};\n", pad, left, right)),
+            AstNodeKind::If(If {
+                ref guard,
+                ref if_block,
+                ref else_block,
+            }) => {
+                f.write_fmt(format_args!("{}if ({}) ", pad, guard))?;
+                if_block.fmt_block(f, indent)?;
+                if let Some(else_block) = else_block {
+                    f.write_fmt(format_args!("{}else ", pad))?;
+                    else_block.fmt_block(f, indent)?;
+                }
+                Ok(())
+            }
+            AstNodeKind::While(While {
+                ref guard,
+                ref block,
+            }) => {
+                f.write_fmt(format_args!("{}while ({}) ", pad, guard))?;
+                block.fmt_block(f, indent)
+            }
+            AstNodeKind::Block(_) => {
+                f.write_str(&pad)?;
+                self.fmt_block(f, indent)
+            }
+            AstNodeKind::Function(Function {
+                ref name,
+                ref params,
+                ref vars,
+                ref statements,
+                ref ret,
+                ref annotations,
+                ref ret_type,
+            }) => {
+                // in ASCII, the only notation of type variables the parser reads back
+                let declared: Vec<&Term> = params
+                    .iter()
+                    .chain(vars)
+                    .filter_map(|x| annotations.iter().find(|(y, _)| x == y))
+                    .map(|(_, t)| t)
+                    .chain(ret_type.as_deref())
+                    .collect();
+                let names = TypePrinter::ascii().print_all(&declared);
+                let names: HashMap<&Term, &String> = declared.into_iter().zip(&names).collect();
+                let join = |nodes: &Vec<AstNode>| {
+                    nodes
+                        .iter()
+                        .map(|x| match annotations.iter().find(|(y, _)| x == y) {
+                            Some((_, t)) => format!("{}: {}", x, names[t]),
+                            None => x.to_string(),
+                        })
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                };
+                match ret_type {
+                    Some(t) => f.write_fmt(format_args!(
+                        "{}({}): {} {{\n",
+                        name,
+                        join(params),
+                        names[t.as_ref()]
+                    ))?,
+                    None => f.write_fmt(format_args!("{}({}) {{\n", name, join(params)))?,
+                }
+                if !vars.is_empty() {
+                    f.write_fmt(format_args!("    var {};\n", join(vars)))?;
+                }
+                for statement in statements {
+                    statement.fmt_indent(f, indent + 1)?;
+                }
+                f.write_fmt(format_args!("    return {};\n}}\n", ret))
+            }
+            AstNodeKind::Program(ref functions) => {
+                for (i, function) in functions.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char('\n')?;
+                    }
+                    function.fmt_indent(f, indent)?;
+                }
+                Ok(())
+            }
+            AstNodeKind::Number(n) => f.write_fmt(format_args!("{}", n)),
+            AstNodeKind::Input => f.write_str("input"),
+            AstNodeKind::Record(ref fields) => {
+                f.write_char('{')?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    f.write_fmt(format_args!("{}: {}", field.name, field.expression))?;
+                }
+                f.write_char('}')
+            }
+            AstNodeKind::Null => f.write_str("null"),
+            AstNodeKind::Alloc(Alloc { ref expr }) => f.write_fmt(format_args!("alloc {}", expr)),
+            AstNodeKind::Ref(Ref { ref id }) => f.write_fmt(format_args!("&{}", id)),
+            AstNodeKind::Deref(Deref { ref atom }) => match atom.kind {
+                AstNodeKind::Expression(_)
+                | AstNodeKind::FieldAccess(_)
+                | AstNodeKind::Alloc(_) => f.write_fmt(format_args!("*({})", atom)),
+                _ => f.write_fmt(format_args!("*{}", atom)),
+            },
+            AstNodeKind::FunApp(FunApp {
+                ref method,
+                ref params,
+            }) => {
+                if let AstNodeKind::Id(_) = method.kind {
+                    f.write_fmt(format_args!("{}(", method))?;
+                } else {
+                    f.write_fmt(format_args!("({})(", method))?;
+                }
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    f.write_fmt(format_args!("{}", param))?;
+                }
+                f.write_char(')')
+            }
+            AstNodeKind::FieldAccess(FieldAccess { ref name, ref path }) => match name.kind {
+                AstNodeKind::Id(_) | AstNodeKind::FieldAccess(_) => {
+                    f.write_fmt(format_args!("{}.{}", name, path))
+                }
+                _ => f.write_fmt(format_args!("({}).{}", name, path)),
+            },
+            AstNodeKind::Expression(BinaryOp {
+                ref op,
+                ref left,
+                ref right,
+            }) => {
+                // left associative, so the right operand needs parentheses on a tie
+                let operand = |node: &AstNode, tie: bool| match node.kind {
+                    AstNodeKind::Expression(BinaryOp { op: ref inner, .. })
+                        if inner.precedence() < op.precedence()
+                            || (tie && inner.precedence() == op.precedence()) =>
+                    {
+                        format!("({})", node)
+                    }
+                    // alloc takes the whole expression on its right
+                    AstNodeKind::Alloc(_) => format!("({})", node),
+                    _ => node.to_string(),
+                };
+                f.write_fmt(format_args!(
+                    "{} {} {}",
+                    operand(left, false),
+                    op,
+                    operand(right, true)
+                ))
+            }
+        }
+    }
+
+    /// print a statement as the body of if/while, always in braces
+    fn fmt_block(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        f.write_str("{\n")?;
+        match self.kind {
+            AstNodeKind::Block(Block { ref exprs }) => {
+                for expr in exprs {
+                    expr.fmt_indent(f, indent + 1)?;
+                }
+            }
+            _ => self.fmt_indent(f, indent + 1)?,
+        }
+        f.write_fmt(format_args!("{}}}\n", "    ".repeat(indent)))
+    }
+}
+
+/// print TIP source
+impl fmt::Display for AstNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indent(f, 0)
+    }
+}
+
+impl Op {
+    fn precedence(&self) -> usize {
+        match self {
+            Op::Gt | Op::Equal => 1,
+            Op::Add | Op::Subtract => 2,
+            Op::Multiply | Op::Divide => 3,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Add => "+",
+            Op::Subtract => "-",
+            Op::Multiply => "*",
+            Op::Divide => "/",
+            Op::Gt => ">",
+            Op::Equal => "==",
+        })
+    }
+}
+
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
 pub enum AstNodeKind {
     Id(String),
@@ -295,18 +581,33 @@ fn parse_expression(expression: Pairs<Rule>) -> AstNode {
     )
 }
 
-fn pair_2_ids(pair: pest::iterators::Pair<Rule>) -> Vec<AstNode> {
-    pair.into_inner().map(build_ast_from_expr).collect()
+/// an annotated id goes to `annotations` with its type
+fn pair_2_ids(
+    pair: pest::iterators::Pair<Rule>,
+    types: &mut TypeBuilder,
+    annotations: &mut Vec<(AstNode, Term)>,
+) -> Vec<AstNode> {
+    let mut ids: Vec<AstNode> = vec![];
+    for pair in pair.into_inner() {
+        match pair.as_rule() {
+            Rule::type_expr => annotations.push((ids.last().unwrap().clone(), types.build(pair))),
+            _ => ids.push(build_ast_from_expr(pair)),
+        }
+    }
+    ids
 }
 
-fn pair_2_vars(pair: pest::iterators::Pair<Rule>) -> Vec<AstNode> {
+fn pair_2_vars(
+    pair: pest::iterators::Pair<Rule>,
+    types: &mut TypeBuilder,
+    annotations: &mut Vec<(AstNode, Term)>,
+) -> Vec<AstNode> {
     pair.into_inner()
-        .map(pair_2_ids)
-        .map(|x| {
+        .map(|x| pair_2_ids(x, types, annotations))
+        .flat_map(|x| {
             // TODO not efficient here
             x.into_iter()
         })
-        .flatten()
         .collect()
 }
 
@@ -421,8 +722,18 @@ fn build_ast_from_expr(pair: pest::iterators::Pair<Rule>) -> AstNode {
         Rule::function => {
             let mut pair = pair.into_inner();
             let name = pair.next().unwrap().as_str().to_string();
-            let params = pair_2_ids(pair.next().unwrap());
-            let vars = pair_2_vars(pair.next().unwrap());
+            let mut types = TypeBuilder::new();
+            let mut annotations = vec![];
+            let params = pair_2_ids(pair.next().unwrap(), &mut types, &mut annotations);
+            let mut next = pair.next().unwrap();
+            let ret_type = if next.as_rule() == Rule::type_expr {
+                let t = types.build(next);
+                next = pair.next().unwrap();
+                Some(Box::new(t))
+            } else {
+                None
+            };
+            let vars = pair_2_vars(next, &mut types, &mut annotations);
             let mut statements: Vec<AstNode> = pair.map(build_ast_from_expr).collect();
             let ret = Box::new(statements.pop().unwrap());
             AstNode {
@@ -434,6 +745,8 @@ fn build_ast_from_expr(pair: pest::iterators::Pair<Rule>) -> AstNode {
                     vars,
                     statements,
                     ret,
+                    annotations,
+                    ret_type,
                 }),
             }
         }
@@ -536,7 +849,9 @@ mod tests {
     use crate::ast_parser::build_ast_from_expr;
     use crate::ast_parser::parse;
     use crate::ast_parser::IdentParser;
+    use crate::ast_parser::{AstNodeKind, Function};
     use crate::pest::Parser;
+    use crate::term::{Cons, Term};
 
     use std::fs;
 
@@ -548,7 +863,7 @@ mod tests {
             if path.is_file() {
                 let content = &fs::read_to_string(&path)?;
                 // dbg!(&path);
-                parse(&content);
+                parse(content);
             }
         }
         Ok(())
@@ -557,8 +872,8 @@ mod tests {
     #[test]
     fn test_fib() -> std::io::Result<()> {
         let path = "/home/lyj/TIP/examples/fib.tip";
-        let content = &fs::read_to_string(&path)?;
-        parse(&content);
+        let content = &fs::read_to_string(path)?;
+        parse(content);
         Ok(())
     }
 
@@ -587,4 +902,29 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_annotations() {
+        let program =
+            parse("swap(p: ↑a, q: &a): int { var t: a, u; t = *p; *p = *q; *q = t; return 0; }");
+        let function = match program.kind {
+            AstNodeKind::Program(ref functions) => functions[0].clone(),
+            _ => unreachable!(),
+        };
+        if let AstNodeKind::Function(Function {
+            ref annotations,
+            ref ret_type,
+            ..
+        }) = function.kind
+        {
+            let names: Vec<String> = annotations.iter().map(|(x, _)| x.to_string()).collect();
+            assert_eq!(names, vec!["p", "q", "t"]);
+            // `a` is the same variable in every annotation
+            assert_eq!(annotations[0].1, annotations[1].1);
+            assert_eq!(ret_type.as_deref(), Some(&Term::Cons(Cons::IntType)));
+        }
+        let printed = program.to_string();
+        assert!(printed.starts_with("swap(p: &a, q: &a): int {\n    var t: a, u;\n"));
+        assert_eq!(parse(&printed).to_string(), printed);
+    }
 }
@@ -0,0 +1,255 @@
+use crate::ast_parser::*;
+use crate::cfg::{address_taken, exprs, ids, Cfg, CfgNode};
+use crate::dataflow::{per_function, Dataflow, DataflowResult, Direction};
+use crate::dfs::Dfs;
+use std::collections::{HashMap, HashSet};
+
+/// push every pure AstNode::Expression of `node` (without position) into `res`
+/// return whether `node` is pure: `input`, `alloc` and calls are not
+fn collect(node: &AstNode, res: &mut HashSet<AstNode>) -> bool {
+    match node.kind {
+        AstNodeKind::Expression(BinaryOp {
+            ref left,
+            ref right,
+            ..
+        }) => {
+            let pure = collect(left, res) & collect(right, res);
+            if pure {
+                res.insert(node.without_position());
+            }
+            pure
+        }
+        AstNodeKind::Input => false,
+        AstNodeKind::Alloc(Alloc { ref expr }) => {
+            collect(expr, res);
+            false
+        }
+        AstNodeKind::FunApp(FunApp {
+            ref method,
+            ref params,
+        }) => {
+            collect(method, res);
+            for param in params {
+                collect(param, res);
+            }
+            false
+        }
+        AstNodeKind::Record(ref fields) => fields
+            .iter()
+            .fold(true, |pure, f| collect(&f.expression, res) & pure),
+        AstNodeKind::Deref(Deref { ref atom }) => collect(atom, res),
+        AstNodeKind::FieldAccess(FieldAccess { ref name, .. }) => collect(name, res),
+        _ => true,
+    }
+}
+
+/// non-trivial pure expressions evaluated by a cfg node, without position
+pub fn subexpressions(node: &CfgNode) -> HashSet<AstNode> {
+    let mut res = HashSet::new();
+    for expr in exprs(node) {
+        collect(expr, &mut res);
+    }
+    res
+}
+
+/// what a cfg node may overwrite
+pub struct Writes {
+    /// names of overwritten variables
+    vars: HashSet<String>,
+    /// heap cells may be overwritten by `*p = e`, `(*p).f = e` or a call
+    heap: bool,
+}
+
+impl Writes {
+    /// escaped: names of variables whose address is taken
+    pub fn new(node: &CfgNode, escaped: &HashSet<String>) -> Self {
+        let mut vars = HashSet::new();
+        let mut heap = exprs(node).iter().any(|e| contains_call(e));
+        if let CfgNode::Stmt(AstNode {
+            kind: AstNodeKind::Assign(Assign { ref left, .. }),
+            ..
+        }) = node
+        {
+            match left.kind {
+                AstNodeKind::Id(ref name) => {
+                    vars.insert(name.clone());
+                }
+                // x.f = e, (x).f = e
+                AstNodeKind::DirectFieldWrite(DirectFieldWrite { id: ref x, .. })
+                | AstNodeKind::IndirectFieldWrite(IndirectFieldWrite { expr: ref x, .. }) => {
+                    match x.kind {
+                        AstNodeKind::Id(ref name) => {
+                            vars.insert(name.clone());
+                        }
+                        _ => heap = true,
+                    }
+                }
+                _ => heap = true,
+            }
+        }
+        if heap {
+            vars.extend(escaped.iter().cloned());
+        }
+        Self { vars, heap }
+    }
+
+    pub fn writes_heap(&self) -> bool {
+        self.heap
+    }
+
+    /// whether the value of `expr` may be changed
+    pub fn kills(&self, expr: &AstNode) -> bool {
+        if self.heap && contains_deref(expr) {
+            return true;
+        }
+        ids(expr).iter().any(|id| match id.kind {
+            AstNodeKind::Id(ref name) => self.vars.contains(name),
+            _ => unreachable!(),
+        })
+    }
+}
+
+/// find AstNode::Deref or AstNode::FunApp
+struct Finder {
+    deref: bool,
+    call: bool,
+}
+
+impl Dfs for Finder {
+    type ResultType = (bool, bool);
+
+    fn new(_: &AstNode) -> Self {
+        Self {
+            deref: false,
+            call: false,
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        match node.kind {
+            AstNodeKind::Deref(_) => self.deref = true,
+            AstNodeKind::FunApp(_) => self.call = true,
+            _ => {}
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        (self.deref, self.call)
+    }
+}
+
+fn contains_deref(expr: &AstNode) -> bool {
+    Finder::work(expr).0
+}
+
+pub fn contains_call(expr: &AstNode) -> bool {
+    Finder::work(expr).1
+}
+
+/// names of the variables whose address is taken in a function
+pub fn escaped_names(cfg: &Cfg, decl: &HashMap<AstNode, AstNode>) -> HashSet<String> {
+    address_taken(&cfg.function, decl)
+        .into_iter()
+        .filter_map(|x| match x.kind {
+            AstNodeKind::Id(name) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// forward must analysis
+/// a fact is the set of available expressions (without position)
+pub struct AvailableExpressions {
+    /// every expression of the function
+    universe: HashSet<AstNode>,
+    escaped: HashSet<String>,
+}
+
+impl AvailableExpressions {
+    pub fn new(cfg: &Cfg, decl: &HashMap<AstNode, AstNode>) -> Self {
+        Self {
+            universe: cfg.nodes.iter().flat_map(subexpressions).collect(),
+            escaped: escaped_names(cfg, decl),
+        }
+    }
+}
+
+impl Dataflow for AvailableExpressions {
+    type Fact = HashSet<AstNode>;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn boundary(&self, _: &Cfg) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn init(&self, _: &Cfg) -> Self::Fact {
+        self.universe.clone()
+    }
+
+    fn join(&self, a: &Self::Fact, b: &Self::Fact) -> Self::Fact {
+        a.intersection(b).cloned().collect()
+    }
+
+    fn transfer(&self, node: &CfgNode, fact: &Self::Fact) -> Self::Fact {
+        let writes = Writes::new(node, &self.escaped);
+        fact.iter()
+            .cloned()
+            .chain(subexpressions(node))
+            .filter(|e| !writes.kills(e))
+            .collect()
+    }
+}
+
+/// available expressions of every function, the key is the function name
+pub fn available_expressions(
+    program: &AstNode,
+) -> HashMap<String, (Cfg, DataflowResult<HashSet<AstNode>>)> {
+    per_function(program, |cfg, decl| {
+        AvailableExpressions::new(cfg, decl).solve(cfg)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::parse;
+    use crate::available_expressions::{available_expressions, subexpressions};
+
+    #[test]
+    fn test_available_expressions() {
+        let source = "main() { var a, b, x, y; a = input; b = input; x = a + b; y = a * b;
+             while (y > a + b) { a = a + 1; x = a + b; } return x; }";
+        let program = parse(source);
+        let res = available_expressions(&program);
+        let (cfg, available) = &res["main"];
+        let a_plus_b = subexpressions(&cfg.nodes[cfg.find(source, "x = a + b;")]);
+        assert_eq!(available.after[cfg.find(source, "y = a * b;")].len(), 2);
+        assert!(available.after[cfg.find(source, "y = a * b;")].is_superset(&a_plus_b));
+        // at the loop head, a * b is killed by a = a + 1
+        assert_eq!(
+            available.before[cfg.find(source, "while (y > a + b)")],
+            a_plus_b
+        );
+        // a + 1 is killed by a = a + 1 itself
+        assert!(available.after[cfg.find(source, "a = a + 1;")].is_empty());
+        // a + b and y > a + b
+        assert_eq!(available.before[cfg.find(source, "return x;")].len(), 2);
+    }
+
+    #[test]
+    fn test_available_expressions_deref() {
+        let source =
+            "main() { var p, x, y; p = alloc 1; x = *p + 1; *p = 2; y = input + 3; return x; }";
+        let program = parse(source);
+        let res = available_expressions(&program);
+        let (cfg, available) = &res["main"];
+        assert_eq!(available.after[cfg.find(source, "x = *p + 1;")].len(), 1);
+        // *p + 1 is killed by *p = 2
+        assert!(available.after[cfg.find(source, "*p = 2;")].is_empty());
+        // input + 3 is never available
+        assert!(available.after[cfg.find(source, "y = input + 3;")].is_empty());
+    }
+}
@@ -0,0 +1,439 @@
+use crate::ast_parser::*;
+use crate::control_flow_analysis::ControlFlowAnalysis;
+use crate::declaration_analysis::DeclarationAnalysis;
+use crate::dfs::Dfs;
+use std::collections::{BTreeSet, HashMap};
+
+/// resolve a call through a function value
+pub trait CallResolver {
+    /// call: AstNode::FunApp
+    /// return AstNode::Function
+    fn resolve(&self, call: &AstNode) -> Vec<AstNode>;
+}
+
+/// a call may invoke any function with the same arity
+pub struct ArityResolver {
+    /// AstNode::Function
+    functions: Vec<AstNode>,
+}
+
+impl ArityResolver {
+    pub fn new(program: &AstNode) -> Self {
+        match program.kind {
+            AstNodeKind::Program(ref functions) => Self {
+                functions: functions.clone(),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl CallResolver for ArityResolver {
+    fn resolve(&self, call: &AstNode) -> Vec<AstNode> {
+        let arity = match call.kind {
+            AstNodeKind::FunApp(FunApp { ref params, .. }) => params.len(),
+            _ => unreachable!(),
+        };
+        self.functions
+            .iter()
+            .filter(|f| match f.kind {
+                AstNodeKind::Function(Function { ref params, .. }) => params.len() == arity,
+                _ => unreachable!(),
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+impl CallResolver for ControlFlowAnalysis {
+    fn resolve(&self, call: &AstNode) -> Vec<AstNode> {
+        self.callees(call)
+    }
+}
+
+/// collect every AstNode::FunApp with the index of its enclosing function
+struct CallCollector {
+    current: usize,
+    calls: Vec<(AstNode, usize)>,
+}
+
+impl Dfs for CallCollector {
+    type ResultType = Vec<(AstNode, usize)>;
+
+    fn new(_: &AstNode) -> Self {
+        Self {
+            current: 0,
+            calls: vec![],
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        if let AstNodeKind::Program(ref functions) = node.kind {
+            for (i, function) in functions.iter().enumerate() {
+                self.current = i;
+                self.dfs(function);
+            }
+            return false;
+        }
+        if let AstNodeKind::FunApp(_) = node.kind {
+            self.calls.push((node.clone(), self.current));
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        self.calls
+    }
+}
+
+/// collect every Id with the index of its enclosing function
+struct ReferenceCollector {
+    current: usize,
+    /// (AstNode::Id, index)
+    references: Vec<(AstNode, usize)>,
+}
+
+impl Dfs for ReferenceCollector {
+    type ResultType = Vec<(AstNode, usize)>;
+
+    fn new(_: &AstNode) -> Self {
+        Self {
+            current: 0,
+            references: vec![],
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        if let AstNodeKind::Program(ref functions) = node.kind {
+            for (i, function) in functions.iter().enumerate() {
+                self.current = i;
+                self.dfs(function);
+            }
+            return false;
+        }
+        if let AstNodeKind::Id(_) = node.kind {
+            self.references.push((node.clone(), self.current));
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        self.references
+    }
+}
+
+/// call graph of a program
+/// functions are referred by their index in the program
+pub struct CallGraph {
+    /// AstNode::Function
+    functions: Vec<AstNode>,
+    /// caller => callees
+    edges: Vec<BTreeSet<usize>>,
+}
+
+impl CallGraph {
+    /// indirect calls are resolved by the control-flow analysis
+    pub fn new(program: &AstNode) -> Self {
+        Self::with_resolver(program, &ControlFlowAnalysis::new(program))
+    }
+
+    /// direct calls are resolved by DeclarationAnalysis, the others by `resolver`
+    pub fn with_resolver(program: &AstNode, resolver: &dyn CallResolver) -> Self {
+        let functions = match program.kind {
+            AstNodeKind::Program(ref functions) => functions.clone(),
+            _ => unreachable!(),
+        };
+        let index: HashMap<AstNode, usize> = functions
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (f.clone(), i))
+            .collect();
+        let decl = DeclarationAnalysis::work(program);
+        let mut edges = vec![BTreeSet::new(); functions.len()];
+        for (call, caller) in CallCollector::work(program) {
+            let callees: Vec<usize> = match call.kind {
+                AstNodeKind::FunApp(FunApp { ref method, .. }) => match decl.get(method) {
+                    Some(f) if index.contains_key(f) => vec![index[f]],
+                    _ => resolver.resolve(&call).iter().map(|f| index[f]).collect(),
+                },
+                _ => unreachable!(),
+            };
+            edges[caller].extend(callees);
+        }
+        Self { functions, edges }
+    }
+
+    /// edges to every function named in a function, called or not, e.g. `twice(f, x)`
+    /// indirect calls are left out, sccs() puts a function after every function it names
+    /// decl: DeclarationAnalysis of the program
+    pub fn with_references(program: &AstNode, decl: &HashMap<AstNode, AstNode>) -> Self {
+        let functions = match program.kind {
+            AstNodeKind::Program(ref functions) => functions.clone(),
+            _ => unreachable!(),
+        };
+        let index: HashMap<AstNode, usize> = functions
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (f.clone(), i))
+            .collect();
+        let mut edges = vec![BTreeSet::new(); functions.len()];
+        for (id, user) in ReferenceCollector::work(program) {
+            if let Some(f) = decl.get(&id) {
+                if let AstNodeKind::Function(_) = f.kind {
+                    edges[user].insert(index[f]);
+                }
+            }
+        }
+        Self { functions, edges }
+    }
+
+    fn name(&self, i: usize) -> &str {
+        match self.functions[i].kind {
+            AstNodeKind::Function(Function { ref name, .. }) => name,
+            _ => unreachable!(),
+        }
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        (0..self.functions.len()).find(|i| self.name(*i) == name)
+    }
+
+    /// functions `function` may call, in program order
+    pub fn callees(&self, function: &str) -> Vec<String> {
+        match self.index(function) {
+            Some(i) => self.edges[i]
+                .iter()
+                .map(|j| self.name(*j).to_string())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// `function` may call itself, directly or not
+    pub fn is_recursive(&self, function: &str) -> bool {
+        match self.index(function) {
+            Some(i) => {
+                self.edges[i].contains(&i)
+                    || self
+                        .sccs()
+                        .iter()
+                        .any(|scc| scc.len() > 1 && scc.iter().any(|f| f == function))
+            }
+            None => false,
+        }
+    }
+
+    /// strongly connected components with Tarjan's algorithm
+    /// a callee comes before its callers, except inside a component
+    pub fn sccs(&self) -> Vec<Vec<String>> {
+        let mut tarjan = Tarjan {
+            edges: &self.edges,
+            index: vec![None; self.functions.len()],
+            low: vec![0; self.functions.len()],
+            stack: vec![],
+            on_stack: vec![false; self.functions.len()],
+            next: 0,
+            sccs: vec![],
+        };
+        for i in 0..self.functions.len() {
+            if tarjan.index[i].is_none() {
+                tarjan.visit(i);
+            }
+        }
+        tarjan
+            .sccs
+            .into_iter()
+            .map(|mut scc| {
+                scc.sort_unstable();
+                scc.iter().map(|i| self.name(*i).to_string()).collect()
+            })
+            .collect()
+    }
+
+    /// every function after all the functions it calls, except for recursion
+    pub fn bottom_up(&self) -> Vec<String> {
+        self.sccs().into_iter().flatten().collect()
+    }
+
+    /// functions never called from main, every function if there is no main
+    pub fn unreachable(&self) -> Vec<String> {
+        let mut reachable = vec![false; self.functions.len()];
+        let mut stack: Vec<usize> = self.index("main").into_iter().collect();
+        while let Some(i) = stack.pop() {
+            if !reachable[i] {
+                reachable[i] = true;
+                stack.extend(self.edges[i].iter().cloned());
+            }
+        }
+        (0..self.functions.len())
+            .filter(|i| !reachable[*i])
+            .map(|i| self.name(i).to_string())
+            .collect()
+    }
+
+    /// Graphviz format
+    pub fn to_dot(&self) -> String {
+        let mut res = String::from("digraph callgraph {\n");
+        for i in 0..self.functions.len() {
+            res.push_str(&format!("    \"{}\";\n", self.name(i)));
+        }
+        for (i, callees) in self.edges.iter().enumerate() {
+            for j in callees {
+                res.push_str(&format!(
+                    "    \"{}\" -> \"{}\";\n",
+                    self.name(i),
+                    self.name(*j)
+                ));
+            }
+        }
+        res.push_str("}\n");
+        res
+    }
+
+    /// {"functions": [..], "edges": [[caller, callee], ..], "sccs": [[..], ..], "unreachable": [..]}
+    /// names are TIP identifiers (ASCII letters, digits and `_`), which never need to be escaped
+    pub fn to_json(&self) -> String {
+        let list = |names: &[String]| {
+            let quoted: Vec<String> = names.iter().map(|x| format!("\"{}\"", x)).collect();
+            format!("[{}]", quoted.join(", "))
+        };
+        let functions: Vec<String> = (0..self.functions.len())
+            .map(|i| self.name(i).to_string())
+            .collect();
+        let edges: Vec<String> = self
+            .edges
+            .iter()
+            .enumerate()
+            .flat_map(|(i, callees)| {
+                callees
+                    .iter()
+                    .map(move |j| list(&[self.name(i).to_string(), self.name(*j).to_string()]))
+            })
+            .collect();
+        let sccs: Vec<String> = self.sccs().iter().map(|scc| list(scc)).collect();
+        format!(
+            "{{\"functions\": {}, \"edges\": [{}], \"sccs\": [{}], \"unreachable\": {}}}",
+            list(&functions),
+            edges.join(", "),
+            sccs.join(", "),
+            list(&self.unreachable())
+        )
+    }
+}
+
+/// state of Tarjan's algorithm
+struct Tarjan<'a> {
+    edges: &'a [BTreeSet<usize>],
+    index: Vec<Option<usize>>,
+    low: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    next: usize,
+    sccs: Vec<Vec<usize>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, v: usize) {
+        self.index[v] = Some(self.next);
+        self.low[v] = self.next;
+        self.next += 1;
+        self.stack.push(v);
+        self.on_stack[v] = true;
+        for &w in self.edges[v].iter() {
+            match self.index[w] {
+                None => {
+                    self.visit(w);
+                    self.low[v] = self.low[v].min(self.low[w]);
+                }
+                Some(i) if self.on_stack[w] => self.low[v] = self.low[v].min(i),
+                _ => {}
+            }
+        }
+        if Some(self.low[v]) == self.index[v] {
+            let mut scc = vec![];
+            loop {
+                let w = self.stack.pop().unwrap();
+                self.on_stack[w] = false;
+                scc.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            self.sccs.push(scc);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::parse;
+    use crate::callgraph::{ArityResolver, CallGraph};
+    use crate::declaration_analysis::DeclarationAnalysis;
+    use crate::dfs::Dfs;
+
+    #[test]
+    fn test_callgraph() {
+        let program = parse(
+            "even(n) { var r; if (n == 0) { r = 1; } else { r = odd(n - 1); } return r; }
+             odd(n) { var r; if (n == 0) { r = 0; } else { r = even(n - 1); } return r; }
+             twice(f, x) { return f(f(x)); }
+             inc(x) { return x + 1; }
+             dead(x) { return x; }
+             main() { return twice(inc, even(4)); }",
+        );
+        let graph = CallGraph::new(&program);
+        assert_eq!(graph.callees("twice"), vec!["inc"]);
+        assert_eq!(
+            graph.sccs(),
+            vec![
+                vec!["even", "odd"],
+                vec!["inc"],
+                vec!["twice"],
+                vec!["dead"],
+                vec!["main"],
+            ]
+        );
+        assert_eq!(
+            graph.bottom_up(),
+            vec!["even", "odd", "inc", "twice", "dead", "main"]
+        );
+        assert_eq!(graph.unreachable(), vec!["dead"]);
+        assert!(graph.is_recursive("odd") && !graph.is_recursive("twice"));
+        assert_eq!(
+            graph.to_dot(),
+            "digraph callgraph {
+    \"even\";
+    \"odd\";
+    \"twice\";
+    \"inc\";
+    \"dead\";
+    \"main\";
+    \"even\" -> \"odd\";
+    \"odd\" -> \"even\";
+    \"twice\" -> \"inc\";
+    \"main\" -> \"even\";
+    \"main\" -> \"twice\";
+}
+"
+        );
+        assert_eq!(
+            graph.to_json(),
+            "{\"functions\": [\"even\", \"odd\", \"twice\", \"inc\", \"dead\", \"main\"], \
+             \"edges\": [[\"even\", \"odd\"], [\"odd\", \"even\"], [\"twice\", \"inc\"], \
+             [\"main\", \"even\"], [\"main\", \"twice\"]], \
+             \"sccs\": [[\"even\", \"odd\"], [\"inc\"], [\"twice\"], [\"dead\"], [\"main\"]], \
+             \"unreachable\": [\"dead\"]}"
+        );
+
+        // every function with one parameter may be passed to twice
+        let graph = CallGraph::with_resolver(&program, &ArityResolver::new(&program));
+        assert_eq!(graph.callees("twice"), vec!["even", "odd", "inc", "dead"]);
+        assert!(graph.unreachable().is_empty());
+
+        // main only names inc, twice calls it
+        let decl = DeclarationAnalysis::work(&program);
+        let graph = CallGraph::with_references(&program, &decl);
+        assert_eq!(graph.callees("main"), vec!["even", "twice", "inc"]);
+        assert!(graph.callees("twice").is_empty());
+    }
+}
@@ -0,0 +1,343 @@
+use crate::ast_parser::*;
+use crate::dfs::Dfs;
+use std::collections::{HashMap, HashSet};
+
+/// a node of the intraprocedural control flow graph
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+pub enum CfgNode {
+    /// parameters and vars are bound here
+    Entry,
+    Exit,
+    /// AstNode::Assign, AstNode::Output, AstNode::Error
+    Stmt(AstNode),
+    /// AstNode::If or AstNode::While, only the guard is evaluated here
+    Guard(AstNode),
+    /// the returned expression
+    Return(AstNode),
+}
+
+impl CfgNode {
+    pub fn ast(&self) -> Option<&AstNode> {
+        match self {
+            CfgNode::Entry | CfgNode::Exit => None,
+            CfgNode::Stmt(node) | CfgNode::Guard(node) | CfgNode::Return(node) => Some(node),
+        }
+    }
+}
+
+/// control flow graph of one AstNode::Function
+/// nodes are referred by their index in `nodes`
+pub struct Cfg {
+    /// AstNode::Function
+    pub function: AstNode,
+    pub nodes: Vec<CfgNode>,
+    pub succ: Vec<Vec<usize>>,
+    pub pred: Vec<Vec<usize>>,
+    /// (guard, successor) => true for the if block or the loop body
+    /// an edge taken by both branches (e.g. `if (x) {}`) has no entry
+    pub branch: HashMap<(usize, usize), bool>,
+    pub entry: usize,
+    pub exit: usize,
+}
+
+/// a dangling edge, with the branch taken if it leaves a guard
+type Edge = (usize, Option<bool>);
+
+impl Cfg {
+    pub fn new(function: &AstNode) -> Self {
+        let mut cfg = Cfg {
+            function: function.clone(),
+            nodes: vec![],
+            succ: vec![],
+            pred: vec![],
+            branch: HashMap::new(),
+            entry: 0,
+            exit: 0,
+        };
+        if let AstNodeKind::Function(Function {
+            ref statements,
+            ref ret,
+            ..
+        }) = function.kind
+        {
+            cfg.entry = cfg.add_node(CfgNode::Entry);
+            let mut last = vec![(cfg.entry, None)];
+            for statement in statements {
+                last = cfg.build(statement, last);
+            }
+            let ret = cfg.add_node(CfgNode::Return(ret.as_ref().clone()));
+            cfg.connect(&last, ret);
+            cfg.exit = cfg.add_node(CfgNode::Exit);
+            cfg.connect(&[(ret, None)], cfg.exit);
+        } else {
+            unreachable!();
+        }
+        cfg
+    }
+
+    /// one Cfg for each function of an AstNode::Program
+    pub fn from_program(program: &AstNode) -> Vec<Cfg> {
+        if let AstNodeKind::Program(ref functions) = program.kind {
+            functions.iter().map(Cfg::new).collect()
+        } else {
+            unreachable!();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// the node of the first statement written as `stmt` in `source`, for tests
+    /// e.g. `x = a + b;`, `if (x > 0)` or `return x;`
+    #[cfg(test)]
+    pub fn find(&self, source: &str, stmt: &str) -> usize {
+        let mut offset = source.find(stmt).unwrap();
+        // a Return node holds the returned expression
+        if stmt.starts_with("return ") {
+            offset += "return ".len();
+        }
+        let line = source[..offset].matches('\n').count() + 1;
+        let col = offset - source[..offset].rfind('\n').map_or(0, |i| i + 1) + 1;
+        (0..self.len())
+            .find(|&i| {
+                self.nodes[i]
+                    .ast()
+                    .is_some_and(|s| (s.line, s.col) == (line, col))
+            })
+            .unwrap()
+    }
+
+    fn add_node(&mut self, node: CfgNode) -> usize {
+        self.nodes.push(node);
+        self.succ.push(vec![]);
+        self.pred.push(vec![]);
+        self.nodes.len() - 1
+    }
+
+    fn connect(&mut self, from: &[Edge], to: usize) {
+        for &(f, branch) in from {
+            if self.succ[f].contains(&to) {
+                // both branches lead to `to`
+                self.branch.remove(&(f, to));
+                continue;
+            }
+            self.succ[f].push(to);
+            self.pred[to].push(f);
+            if let Some(b) = branch {
+                self.branch.insert((f, to), b);
+            }
+        }
+    }
+
+    /// from: edges flowing into `statement`
+    /// return edges flowing out of `statement`
+    fn build(&mut self, statement: &AstNode, from: Vec<Edge>) -> Vec<Edge> {
+        match statement.kind {
+            AstNodeKind::Assign(_) | AstNodeKind::Output(_) | AstNodeKind::Error(_) => {
+                let n = self.add_node(CfgNode::Stmt(statement.clone()));
+                self.connect(&from, n);
+                vec![(n, None)]
+            }
+            AstNodeKind::Block(Block { ref exprs }) => {
+                let mut last = from;
+                for expr in exprs {
+                    last = self.build(expr, last);
+                }
+                last
+            }
+            AstNodeKind::If(If {
+                ref if_block,
+                ref else_block,
+                ..
+            }) => {
+                let guard = self.add_node(CfgNode::Guard(statement.clone()));
+                self.connect(&from, guard);
+                let mut last = self.build(if_block, vec![(guard, Some(true))]);
+                match else_block {
+                    Some(else_block) => {
+                        last.extend(self.build(else_block, vec![(guard, Some(false))]))
+                    }
+                    None => last.push((guard, Some(false))),
+                }
+                last
+            }
+            AstNodeKind::While(While { ref block, .. }) => {
+                let guard = self.add_node(CfgNode::Guard(statement.clone()));
+                self.connect(&from, guard);
+                let last = self.build(block, vec![(guard, Some(true))]);
+                self.connect(&last, guard);
+                vec![(guard, Some(false))]
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// collect every Id read in an expression
+/// `&x` doesn't read x
+struct IdCollector {
+    ids: Vec<AstNode>,
+}
+
+impl Dfs for IdCollector {
+    type ResultType = Vec<AstNode>;
+
+    fn new(_: &AstNode) -> Self {
+        Self { ids: vec![] }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        match node.kind {
+            AstNodeKind::Id(_) => {
+                self.ids.push(node.clone());
+                false
+            }
+            AstNodeKind::Ref(_) => false,
+            _ => true,
+        }
+    }
+
+    fn finish(self) -> Self::ResultType {
+        self.ids
+    }
+}
+
+/// every Id read in an expression, in evaluation order
+pub fn ids(expr: &AstNode) -> Vec<AstNode> {
+    IdCollector::work(expr)
+}
+
+/// expressions evaluated by a cfg node
+/// the target of `x = e` is not included, but the pointer of `*p = e` is
+pub fn exprs(node: &CfgNode) -> Vec<&AstNode> {
+    let mut exprs: Vec<&AstNode> = vec![];
+    match node {
+        CfgNode::Entry | CfgNode::Exit => {}
+        CfgNode::Stmt(stmt) => match stmt.kind {
+            AstNodeKind::Assign(Assign {
+                ref left,
+                ref right,
+            }) => {
+                match left.kind {
+                    AstNodeKind::Id(_) => {}
+                    // x.f = e reads the rest of x
+                    AstNodeKind::DirectFieldWrite(DirectFieldWrite { ref id, .. }) => {
+                        exprs.push(id)
+                    }
+                    AstNodeKind::IndirectFieldWrite(IndirectFieldWrite { ref expr, .. }) => {
+                        exprs.push(expr)
+                    }
+                    AstNodeKind::DerefWrite(DerefWrite { ref expr }) => exprs.push(expr),
+                    _ => unreachable!(),
+                }
+                exprs.push(right);
+            }
+            AstNodeKind::Output(Output { ref expr }) => exprs.push(expr),
+            AstNodeKind::Error(Error { ref expr }) => exprs.push(expr),
+            _ => unreachable!(),
+        },
+        CfgNode::Guard(stmt) => match stmt.kind {
+            AstNodeKind::If(If { ref guard, .. }) => exprs.push(guard),
+            AstNodeKind::While(While { ref guard, .. }) => exprs.push(guard),
+            _ => unreachable!(),
+        },
+        CfgNode::Return(expr) => exprs.push(expr),
+    }
+    exprs
+}
+
+/// variables (declaration of params and vars) read by a cfg node
+/// decl: generate from DeclarationAnalysis
+pub fn uses(node: &CfgNode, decl: &HashMap<AstNode, AstNode>) -> HashSet<AstNode> {
+    exprs(node)
+        .into_iter()
+        .flat_map(ids)
+        .filter_map(|id| decl.get(&id).cloned())
+        .filter(|d| matches!(d.kind, AstNodeKind::Id(_)))
+        .collect()
+}
+
+/// the variable overwritten by `x = e`
+pub fn def(node: &CfgNode, decl: &HashMap<AstNode, AstNode>) -> Option<AstNode> {
+    if let CfgNode::Stmt(AstNode {
+        kind: AstNodeKind::Assign(Assign { ref left, .. }),
+        ..
+    }) = node
+    {
+        if let AstNodeKind::Id(_) = left.kind {
+            return decl.get(left).cloned();
+        }
+    }
+    None
+}
+
+/// collect every AstNode::Ref target
+struct RefCollector {
+    ids: Vec<AstNode>,
+}
+
+impl Dfs for RefCollector {
+    type ResultType = Vec<AstNode>;
+
+    fn new(_: &AstNode) -> Self {
+        Self { ids: vec![] }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        if let AstNodeKind::Ref(Ref { ref id }) = node.kind {
+            self.ids.push(id.as_ref().clone());
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        self.ids
+    }
+}
+
+/// variables whose address is taken by `&x` somewhere in `node`
+pub fn address_taken(node: &AstNode, decl: &HashMap<AstNode, AstNode>) -> HashSet<AstNode> {
+    RefCollector::work(node)
+        .iter()
+        .filter_map(|id| decl.get(id).cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::parse;
+    use crate::cfg::{Cfg, CfgNode};
+
+    #[test]
+    fn test_while_cfg() {
+        let program =
+            parse("main() { var x; x = input; while (x > 0) { x = x - 1; } output x; return 0; }");
+        let cfg = &Cfg::from_program(&program)[0];
+        // entry, x=input, guard, x=x-1, output, return, exit
+        assert_eq!(cfg.len(), 7);
+        let guard = cfg
+            .nodes
+            .iter()
+            .position(|n| matches!(n, CfgNode::Guard(_)))
+            .unwrap();
+        assert_eq!(cfg.pred[guard].len(), 2);
+        assert_eq!(cfg.succ[guard].len(), 2);
+        assert_eq!(cfg.pred[cfg.exit].len(), 1);
+        assert!(cfg.branch[&(guard, guard + 1)]);
+        assert!(!cfg.branch[&(guard, guard + 2)]);
+    }
+
+    #[test]
+    fn test_empty_branch_cfg() {
+        let program = parse("main() { var x; if (x) { } output x; return 0; }");
+        let cfg = &Cfg::from_program(&program)[0];
+        // the guard reaches output by both branches
+        assert_eq!(cfg.succ[1].len(), 1);
+        assert!(cfg.branch.is_empty());
+    }
+}
@@ -0,0 +1,343 @@
+use crate::ast_parser::*;
+use crate::cfg::Cfg;
+use crate::dataflow::Dataflow;
+use crate::declaration_analysis::DeclarationAnalysis;
+use crate::dfs::Dfs;
+use crate::value_analysis::{ValueAnalysis, ValueLattice, ValueState};
+use std::collections::HashMap;
+
+/// flat lattice of i32
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Constant {
+    Top,
+    Const(i32),
+    Bot,
+}
+
+impl ValueLattice for Constant {
+    fn top() -> Self {
+        Constant::Top
+    }
+
+    fn bot() -> Self {
+        Constant::Bot
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Constant::Bot, x) | (x, Constant::Bot) => *x,
+            (Constant::Const(a), Constant::Const(b)) if a == b => *self,
+            _ => Constant::Top,
+        }
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Constant::Top, x) | (x, Constant::Top) => *x,
+            (Constant::Const(a), Constant::Const(b)) if a == b => *self,
+            _ => Constant::Bot,
+        }
+    }
+
+    fn constant(n: i32) -> Self {
+        Constant::Const(n)
+    }
+
+    /// the flat lattice can't express a range
+    fn greater_than(v: &Self) -> Self {
+        match v {
+            Constant::Bot | Constant::Const(i32::MAX) => Constant::Bot,
+            _ => Constant::Top,
+        }
+    }
+
+    fn less_than(v: &Self) -> Self {
+        match v {
+            Constant::Bot | Constant::Const(i32::MIN) => Constant::Bot,
+            _ => Constant::Top,
+        }
+    }
+
+    fn remove(&self, v: &Self) -> Self {
+        match (self, v) {
+            (Constant::Const(a), Constant::Const(b)) if a == b => Constant::Bot,
+            _ => *self,
+        }
+    }
+
+    /// arithmetic wraps around like the TIP interpreter
+    /// division by zero is a runtime error, so it has no value
+    fn binary(op: &Op, left: &Self, right: &Self) -> Self {
+        match (left, right) {
+            (Constant::Bot, _) | (_, Constant::Bot) => Constant::Bot,
+            (Constant::Const(a), Constant::Const(b)) => match op {
+                Op::Add => Constant::Const(a.wrapping_add(*b)),
+                Op::Subtract => Constant::Const(a.wrapping_sub(*b)),
+                Op::Multiply => Constant::Const(a.wrapping_mul(*b)),
+                Op::Divide => {
+                    if *b == 0 {
+                        Constant::Bot
+                    } else {
+                        Constant::Const(a.wrapping_div(*b))
+                    }
+                }
+                Op::Gt => Constant::Const((a > b) as i32),
+                Op::Equal => Constant::Const((a == b) as i32),
+            },
+            _ => Constant::Top,
+        }
+    }
+}
+
+/// rewrite a program with the result of constant propagation
+/// expressions with a known value become AstNode::Number,
+/// if/while with a known guard are folded
+struct ConstantFolding<'a> {
+    analysis: ValueAnalysis<'a, Constant>,
+    /// state before every statement of the function
+    states: HashMap<AstNode, ValueState<Constant>>,
+}
+
+impl<'a> ConstantFolding<'a> {
+    fn new(cfg: &Cfg, decl: &'a HashMap<AstNode, AstNode>) -> Self {
+        let analysis = ValueAnalysis::new(cfg, decl);
+        let result = analysis.solve(cfg);
+        let mut states = HashMap::new();
+        for (i, node) in cfg.nodes.iter().enumerate() {
+            if let Some(stmt) = node.ast() {
+                states.insert(stmt.clone(), result.before[i].clone());
+            }
+        }
+        Self { analysis, states }
+    }
+
+    fn expression(&self, expr: &AstNode, state: &ValueState<Constant>) -> AstNode {
+        let (line, col) = (expr.line, expr.col);
+        if let Constant::Const(n) = self.analysis.eval(expr, state) {
+            return AstNode {
+                kind: AstNodeKind::Number(n),
+                line,
+                col,
+            };
+        }
+        let fold = |e: &AstNode| Box::new(self.expression(e, state));
+        let kind = match expr.kind {
+            AstNodeKind::Record(ref fields) => AstNodeKind::Record(
+                fields
+                    .iter()
+                    .map(|f| Field {
+                        name: f.name.clone(),
+                        expression: fold(&f.expression),
+                    })
+                    .collect(),
+            ),
+            AstNodeKind::Alloc(Alloc { ref expr }) => {
+                AstNodeKind::Alloc(Alloc { expr: fold(expr) })
+            }
+            AstNodeKind::Deref(Deref { ref atom }) => {
+                AstNodeKind::Deref(Deref { atom: fold(atom) })
+            }
+            AstNodeKind::FunApp(FunApp {
+                ref method,
+                ref params,
+            }) => AstNodeKind::FunApp(FunApp {
+                method: fold(method),
+                params: params.iter().map(|p| self.expression(p, state)).collect(),
+            }),
+            AstNodeKind::FieldAccess(FieldAccess { ref name, ref path }) => {
+                AstNodeKind::FieldAccess(FieldAccess {
+                    name: fold(name),
+                    path: path.clone(),
+                })
+            }
+            AstNodeKind::Expression(BinaryOp {
+                ref op,
+                ref left,
+                ref right,
+            }) => AstNodeKind::Expression(BinaryOp {
+                op: op.clone(),
+                left: fold(left),
+                right: fold(right),
+            }),
+            // Id, Ref, Input, Null, unknown Number
+            _ => expr.kind.clone(),
+        };
+        AstNode { kind, line, col }
+    }
+
+    /// None: the statement is removed
+    fn statement(&self, stmt: &AstNode) -> Option<AstNode> {
+        let (line, col) = (stmt.line, stmt.col);
+        let kind = match stmt.kind {
+            AstNodeKind::Assign(Assign {
+                ref left,
+                ref right,
+            }) => {
+                let state = &self.states[stmt];
+                let left = match left.kind {
+                    AstNodeKind::IndirectFieldWrite(IndirectFieldWrite {
+                        ref expr,
+                        ref field,
+                    }) => AstNode {
+                        kind: AstNodeKind::IndirectFieldWrite(IndirectFieldWrite {
+                            expr: Box::new(self.expression(expr, state)),
+                            field: field.clone(),
+                        }),
+                        ..left.as_ref().clone()
+                    },
+                    AstNodeKind::DerefWrite(DerefWrite { ref expr }) => AstNode {
+                        kind: AstNodeKind::DerefWrite(DerefWrite {
+                            expr: Box::new(self.expression(expr, state)),
+                        }),
+                        ..left.as_ref().clone()
+                    },
+                    _ => left.as_ref().clone(),
+                };
+                AstNodeKind::Assign(Assign {
+                    left: Box::new(left),
+                    right: Box::new(self.expression(right, state)),
+                })
+            }
+            AstNodeKind::Output(Output { ref expr }) => AstNodeKind::Output(Output {
+                expr: Box::new(self.expression(expr, &self.states[stmt])),
+            }),
+            AstNodeKind::Error(Error { ref expr }) => AstNodeKind::Error(Error {
+                expr: Box::new(self.expression(expr, &self.states[stmt])),
+            }),
+            AstNodeKind::If(If {
+                ref guard,
+                ref if_block,
+                ref else_block,
+            }) => {
+                let guard = self.expression(guard, &self.states[stmt]);
+                match guard.kind {
+                    AstNodeKind::Number(0) => {
+                        return else_block.as_ref().and_then(|x| self.statement(x));
+                    }
+                    AstNodeKind::Number(_) => return self.statement(if_block),
+                    _ => AstNodeKind::If(If {
+                        guard: Box::new(guard),
+                        if_block: Box::new(self.block(if_block)),
+                        else_block: else_block.as_ref().map(|x| Box::new(self.block(x))),
+                    }),
+                }
+            }
+            AstNodeKind::While(While {
+                ref guard,
+                ref block,
+            }) => {
+                let guard = self.expression(guard, &self.states[stmt]);
+                if let AstNodeKind::Number(0) = guard.kind {
+                    return None;
+                }
+                AstNodeKind::While(While {
+                    guard: Box::new(guard),
+                    block: Box::new(self.block(block)),
+                })
+            }
+            AstNodeKind::Block(Block { ref exprs }) => AstNodeKind::Block(Block {
+                exprs: exprs.iter().filter_map(|x| self.statement(x)).collect(),
+            }),
+            _ => unreachable!(),
+        };
+        Some(AstNode { kind, line, col })
+    }
+
+    /// body of if/while, a removed statement becomes an empty block
+    fn block(&self, stmt: &AstNode) -> AstNode {
+        self.statement(stmt).unwrap_or_else(|| AstNode {
+            kind: AstNodeKind::Block(Block { exprs: vec![] }),
+            ..stmt.clone()
+        })
+    }
+
+    fn function(&self, function: &AstNode) -> AstNode {
+        if let AstNodeKind::Function(ref f) = function.kind {
+            let ret = &f.ret;
+            AstNode {
+                kind: AstNodeKind::Function(Function {
+                    statements: f
+                        .statements
+                        .iter()
+                        .filter_map(|x| self.statement(x))
+                        .collect(),
+                    ret: Box::new(self.expression(ret, &self.states[ret])),
+                    ..f.clone()
+                }),
+                ..function.clone()
+            }
+        } else {
+            unreachable!();
+        }
+    }
+}
+
+/// constant propagation and folding of a whole program
+/// print the result with `{}` to get the simplified TIP source
+pub fn fold_constants(program: &AstNode) -> AstNode {
+    let decl = DeclarationAnalysis::work(program);
+    let functions = Cfg::from_program(program)
+        .iter()
+        .map(|cfg| ConstantFolding::new(cfg, &decl).function(&cfg.function))
+        .collect();
+    AstNode {
+        kind: AstNodeKind::Program(functions),
+        ..program.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::parse;
+    use crate::constant_propagation::{fold_constants, Constant};
+    use crate::value_analysis::{value_analysis, ValueLattice};
+
+    #[test]
+    fn test_constant_lattice() {
+        use Constant::*;
+        assert_eq!(Const(1).join(&Const(1)), Const(1));
+        assert_eq!(Const(1).join(&Const(2)), Top);
+        assert_eq!(Bot.join(&Const(2)), Const(2));
+        assert_eq!(
+            Constant::binary(&crate::ast_parser::Op::Add, &Const(i32::MAX), &Const(1)),
+            Const(i32::MIN)
+        );
+        assert_eq!(
+            Constant::binary(&crate::ast_parser::Op::Divide, &Const(1), &Const(0)),
+            Bot
+        );
+    }
+
+    #[test]
+    fn test_constant_propagation() {
+        let source = "main() { var x, y, z; x = 2; y = x * 3; z = input;
+             if (y > 5) { z = y + 1; } else { z = 0; }
+             while (x == 3) { output z; }
+             return z + y; }";
+        let program = parse(source);
+        let res = value_analysis::<Constant>(&program);
+        let (cfg, values) = &res["main"];
+        let z = values.before[cfg.find(source, "return z + y;")]
+            .iter()
+            .find(|(k, _)| k.to_string() == "z")
+            .unwrap()
+            .1;
+        // the else branch is infeasible
+        assert_eq!(z, &Constant::Const(7));
+
+        let expected = "main() {
+    var x, y, z;
+    x = 2;
+    y = 6;
+    z = input;
+    {
+        z = 7;
+    }
+    return 13;
+}
+";
+        assert_eq!(fold_constants(&program).to_string(), expected);
+        // the simplified program is still valid TIP
+        assert_eq!(parse(expected).to_string(), expected);
+    }
+}
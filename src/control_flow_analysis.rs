@@ -0,0 +1,242 @@
+use crate::ast_parser::*;
+use crate::cubic_solver::CubicSolver;
+use crate::declaration_analysis::DeclarationAnalysis;
+use crate::dfs::Dfs;
+use std::collections::{HashMap, HashSet};
+
+/// constraint variable of the control-flow analysis
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+enum Node {
+    /// param or var (declaration)
+    Var(AstNode),
+    /// value of an expression
+    Expr(AstNode),
+    /// returned value of an AstNode::Function
+    Ret(AstNode),
+    /// every cell of the heap and every variable whose address is taken
+    Heap,
+}
+
+/// generate the constraints of 0-CFA, see the TIP book
+/// ⟦e⟧ is the set of functions e may evaluate to
+struct CfaConstraints {
+    solver: CubicSolver<AstNode, Node>,
+    // generate from DeclarationAnalysis
+    decl: HashMap<AstNode, AstNode>,
+    /// AstNode::Function
+    functions: Vec<AstNode>,
+    /// the function being visited
+    current: Option<AstNode>,
+    /// (AstNode::FunApp, enclosing AstNode::Function)
+    calls: Vec<(AstNode, AstNode)>,
+}
+
+impl CfaConstraints {
+    fn node(&self, node: &AstNode) -> Node {
+        match self.decl.get(node) {
+            Some(x) if !matches!(x.kind, AstNodeKind::Function(_)) => Node::Var(x.clone()),
+            _ => Node::Expr(node.clone()),
+        }
+    }
+}
+
+impl Dfs for CfaConstraints {
+    type ResultType = ControlFlowAnalysis;
+
+    fn new(node: &AstNode) -> Self {
+        let functions = match node.kind {
+            AstNodeKind::Program(ref functions) => functions.clone(),
+            _ => unreachable!(),
+        };
+        Self {
+            solver: CubicSolver::new(),
+            decl: DeclarationAnalysis::work(node),
+            functions,
+            current: None,
+            calls: vec![],
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        match node.kind {
+            // a function name evaluates to the function
+            AstNodeKind::Id(_) => {
+                if let Some(f) = self.decl.get(node) {
+                    if let AstNodeKind::Function(_) = f.kind {
+                        self.solver.add_constant(f, &self.node(node));
+                    }
+                }
+            }
+            AstNodeKind::Assign(Assign {
+                ref left,
+                ref right,
+            }) => {
+                let right = self.node(right);
+                match left.kind {
+                    AstNodeKind::Id(_) => self.solver.add_subset(&right, &self.node(left)),
+                    AstNodeKind::DirectFieldWrite(DirectFieldWrite { ref id, .. }) => {
+                        self.solver.add_subset(&right, &self.node(id))
+                    }
+                    AstNodeKind::DerefWrite(_) => self.solver.add_subset(&right, &Node::Heap),
+                    AstNodeKind::IndirectFieldWrite(IndirectFieldWrite { ref expr, .. }) => {
+                        match expr.kind {
+                            AstNodeKind::Deref(_) => self.solver.add_subset(&right, &Node::Heap),
+                            _ => self.solver.add_subset(&right, &self.node(expr)),
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            // pointers are not tracked, the heap is one big cell
+            AstNodeKind::Ref(Ref { ref id }) => {
+                let x = self.node(id);
+                self.solver.add_subset(&x, &Node::Heap);
+                self.solver.add_subset(&Node::Heap, &x);
+            }
+            AstNodeKind::Alloc(Alloc { ref expr }) => {
+                self.solver.add_subset(&self.node(expr), &Node::Heap);
+            }
+            AstNodeKind::Deref(_) => {
+                self.solver.add_subset(&Node::Heap, &self.node(node));
+            }
+            AstNodeKind::Record(ref fields) => {
+                for field in fields {
+                    self.solver
+                        .add_subset(&self.node(&field.expression), &self.node(node));
+                }
+            }
+            AstNodeKind::FieldAccess(FieldAccess { ref name, .. }) => {
+                self.solver.add_subset(&self.node(name), &self.node(node));
+            }
+            // f ∈ ⟦method⟧ ⇒ ⟦arg_i⟧ ⊆ ⟦param_i⟧ and ⟦ret f⟧ ⊆ ⟦call⟧
+            AstNodeKind::FunApp(FunApp {
+                ref method,
+                ref params,
+            }) => {
+                let m = self.node(method);
+                for f in &self.functions {
+                    if let AstNodeKind::Function(Function {
+                        params: ref formals,
+                        ..
+                    }) = f.kind
+                    {
+                        if formals.len() != params.len() {
+                            continue;
+                        }
+                        for (arg, param) in params.iter().zip(formals) {
+                            self.solver.add_conditional(
+                                f,
+                                &m,
+                                &self.node(arg),
+                                &Node::Var(param.clone()),
+                            );
+                        }
+                        self.solver
+                            .add_conditional(f, &m, &Node::Ret(f.clone()), &self.node(node));
+                    }
+                }
+                let current = self.current.clone().unwrap();
+                self.calls.push((node.clone(), current));
+            }
+            AstNodeKind::Function(Function { ref ret, .. }) => {
+                self.current = Some(node.clone());
+                self.solver
+                    .add_subset(&self.node(ret), &Node::Ret(node.clone()));
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        let mut callees = HashMap::new();
+        let mut callers = HashMap::new();
+        for (call, caller) in &self.calls {
+            if let AstNodeKind::FunApp(FunApp { ref method, .. }) = call.kind {
+                let mut functions: Vec<AstNode> =
+                    self.solver.get(&self.node(method)).into_iter().collect();
+                functions.sort_by_key(|f| (f.line, f.col));
+                callers.insert(call.clone(), caller.clone());
+                callees.insert(call.clone(), functions);
+            }
+        }
+        ControlFlowAnalysis { callees, callers }
+    }
+}
+
+/// functions every call site may invoke
+/// a call through a parameter or a variable is resolved too
+pub struct ControlFlowAnalysis {
+    /// AstNode::FunApp => AstNode::Function, in source order
+    callees: HashMap<AstNode, Vec<AstNode>>,
+    /// AstNode::FunApp => enclosing AstNode::Function
+    callers: HashMap<AstNode, AstNode>,
+}
+
+impl ControlFlowAnalysis {
+    pub fn new(program: &AstNode) -> Self {
+        CfaConstraints::work(program)
+    }
+
+    /// call: AstNode::FunApp
+    pub fn callees(&self, call: &AstNode) -> Vec<AstNode> {
+        self.callees.get(call).cloned().unwrap_or_default()
+    }
+
+    /// caller => callees, both AstNode::Function
+    pub fn call_graph(&self) -> HashMap<AstNode, HashSet<AstNode>> {
+        let mut res: HashMap<AstNode, HashSet<AstNode>> = HashMap::new();
+        for (call, caller) in &self.callers {
+            res.entry(caller.clone())
+                .or_default()
+                .extend(self.callees(call));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::*;
+    use crate::control_flow_analysis::ControlFlowAnalysis;
+
+    #[test]
+    fn test_control_flow_analysis() {
+        let program = parse(
+            "inc(x) { return x + 1; }
+             dec(x) { return x - 1; }
+             ide(x) { return x; }
+             apply(f, x) { return f(x); }
+             main() { var g; g = inc; if (input) { g = dec; } return apply(g, 1) + ide(2); }",
+        );
+        let name = |f: &AstNode| match f.kind {
+            AstNodeKind::Function(Function { ref name, .. }) => name.clone(),
+            _ => unreachable!(),
+        };
+        let cfa = ControlFlowAnalysis::new(&program);
+        let mut graph: Vec<(String, Vec<String>)> = cfa
+            .call_graph()
+            .iter()
+            .map(|(caller, callees)| {
+                let mut callees: Vec<String> = callees.iter().map(name).collect();
+                callees.sort();
+                (name(caller), callees)
+            })
+            .collect();
+        graph.sort();
+        // ide has the same arity as inc and dec, but is never passed to apply
+        assert_eq!(
+            graph,
+            vec![
+                (
+                    "apply".to_string(),
+                    vec!["dec".to_string(), "inc".to_string()]
+                ),
+                (
+                    "main".to_string(),
+                    vec!["apply".to_string(), "ide".to_string()]
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// solver of set constraints over tokens T and variables V:
+/// t ∈ ⟦x⟧, ⟦x⟧ ⊆ ⟦y⟧ and t ∈ ⟦x⟧ ⇒ ⟦y⟧ ⊆ ⟦z⟧
+/// O(n^3) in the number of variables, see the TIP book
+pub struct CubicSolver<T, V> {
+    sol: HashMap<V, HashSet<T>>,
+    /// x => every y with ⟦x⟧ ⊆ ⟦y⟧
+    succ: HashMap<V, HashSet<V>>,
+    /// x => t => every (y, z) waiting for t ∈ ⟦x⟧
+    cond: HashMap<V, HashMap<T, Vec<(V, V)>>>,
+    /// t was just added to ⟦x⟧
+    worklist: Vec<(T, V)>,
+}
+
+impl<T: Clone + Eq + Hash, V: Clone + Eq + Hash> CubicSolver<T, V> {
+    pub fn new() -> Self {
+        Self {
+            sol: HashMap::new(),
+            succ: HashMap::new(),
+            cond: HashMap::new(),
+            worklist: vec![],
+        }
+    }
+
+    /// t ∈ ⟦x⟧
+    pub fn add_constant(&mut self, t: &T, x: &V) {
+        self.add_token(t.clone(), x.clone());
+        self.propagate();
+    }
+
+    /// ⟦x⟧ ⊆ ⟦y⟧
+    pub fn add_subset(&mut self, x: &V, y: &V) {
+        self.add_edge(x.clone(), y.clone());
+        self.propagate();
+    }
+
+    /// t ∈ ⟦x⟧ ⇒ ⟦y⟧ ⊆ ⟦z⟧
+    pub fn add_conditional(&mut self, t: &T, x: &V, y: &V, z: &V) {
+        if self.get(x).contains(t) {
+            self.add_subset(y, z);
+        } else {
+            self.cond
+                .entry(x.clone())
+                .or_default()
+                .entry(t.clone())
+                .or_default()
+                .push((y.clone(), z.clone()));
+        }
+    }
+
+    /// current solution of x
+    pub fn get(&self, x: &V) -> HashSet<T> {
+        self.sol.get(x).cloned().unwrap_or_default()
+    }
+
+    pub fn solution(self) -> HashMap<V, HashSet<T>> {
+        self.sol
+    }
+
+    fn add_token(&mut self, t: T, x: V) {
+        if self.sol.entry(x.clone()).or_default().insert(t.clone()) {
+            self.worklist.push((t, x));
+        }
+    }
+
+    fn add_edge(&mut self, x: V, y: V) {
+        if x != y && self.succ.entry(x.clone()).or_default().insert(y.clone()) {
+            for t in self.get(&x) {
+                self.add_token(t, y.clone());
+            }
+        }
+    }
+
+    fn propagate(&mut self) {
+        while let Some((t, x)) = self.worklist.pop() {
+            let pending = self.cond.get_mut(&x).and_then(|c| c.remove(&t));
+            for (y, z) in pending.unwrap_or_default() {
+                self.add_edge(y, z);
+            }
+            let succ: Vec<V> = self.succ.get(&x).into_iter().flatten().cloned().collect();
+            for y in succ {
+                self.add_token(t.clone(), y);
+            }
+        }
+    }
+}
+
+impl<T: Clone + Eq + Hash, V: Clone + Eq + Hash> Default for CubicSolver<T, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cubic_solver::CubicSolver;
+
+    #[test]
+    fn test_cubic_solver() {
+        let mut solver = CubicSolver::new();
+        solver.add_conditional(&"t", &"x", &"y", &"z");
+        solver.add_constant(&"u", &"y");
+        solver.add_subset(&"z", &"x");
+        assert!(solver.get(&"z").is_empty());
+        // t ∈ x enables y ⊆ z, then u flows y -> z -> x
+        solver.add_constant(&"t", &"x");
+        assert_eq!(solver.get(&"x"), vec!["t", "u"].into_iter().collect());
+        assert_eq!(solver.get(&"z"), vec!["u"].into_iter().collect());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::ast_parser::*;
+use crate::cfg::{Cfg, CfgNode};
+use crate::declaration_analysis::DeclarationAnalysis;
+use crate::dfs::Dfs;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+/// facts of every cfg node, indexed like Cfg::nodes
+/// before/after are in program order, whatever the direction is
+#[derive(Debug, Clone)]
+pub struct DataflowResult<F> {
+    pub before: Vec<F>,
+    pub after: Vec<F>,
+}
+
+/// monotone framework
+/// may analysis: init is the empty set, join is union
+/// must analysis: init is the full set, join is intersection
+pub trait Dataflow {
+    type Fact: Clone + PartialEq;
+
+    fn direction(&self) -> Direction;
+
+    /// fact at Cfg::entry (forward) or Cfg::exit (backward)
+    fn boundary(&self, cfg: &Cfg) -> Self::Fact;
+
+    /// fact every other node starts with
+    fn init(&self, cfg: &Cfg) -> Self::Fact;
+
+    fn join(&self, a: &Self::Fact, b: &Self::Fact) -> Self::Fact;
+
+    fn transfer(&self, node: &CfgNode, fact: &Self::Fact) -> Self::Fact;
+
+    /// applied to the fact flowing along an edge leaving `from`
+    /// branch: the branch taken if `from` is a guard
+    fn transfer_edge(
+        &self,
+        _from: &CfgNode,
+        _branch: Option<bool>,
+        fact: &Self::Fact,
+    ) -> Self::Fact {
+        fact.clone()
+    }
+
+    /// applied to the fact flowing into a node
+    /// old is the previous fact, new is the join of the incoming facts
+    /// lattices with infinite height widen at loop heads to terminate
+    fn widen(&self, _node: &CfgNode, _old: &Self::Fact, new: &Self::Fact) -> Self::Fact {
+        new.clone()
+    }
+
+    /// worklist algorithm
+    fn solve(&self, cfg: &Cfg) -> DataflowResult<Self::Fact> {
+        let forward = self.direction() == Direction::Forward;
+        let (start, flow_out) = if forward {
+            (cfg.entry, &cfg.succ)
+        } else {
+            (cfg.exit, &cfg.pred)
+        };
+        // facts flowing into/out of a node, following the direction
+        let mut input: Vec<Self::Fact> = vec![self.init(cfg); cfg.len()];
+        let mut output: Vec<Self::Fact> = vec![self.init(cfg); cfg.len()];
+        input[start] = self.boundary(cfg);
+
+        let mut worklist: VecDeque<usize> = (0..cfg.len()).collect();
+        if !forward {
+            worklist = worklist.into_iter().rev().collect();
+        }
+        while let Some(n) = worklist.pop_front() {
+            if n != start {
+                if let Some(joined) = incoming(self, cfg, n, &output) {
+                    input[n] = self.widen(&cfg.nodes[n], &input[n], &joined);
+                }
+            }
+            let out = self.transfer(&cfg.nodes[n], &input[n]);
+            if out != output[n] {
+                output[n] = out;
+                for &s in &flow_out[n] {
+                    if !worklist.contains(&s) {
+                        worklist.push_back(s);
+                    }
+                }
+            }
+        }
+
+        if forward {
+            DataflowResult {
+                before: input,
+                after: output,
+            }
+        } else {
+            DataflowResult {
+                before: output,
+                after: input,
+            }
+        }
+    }
+
+    /// recover precision lost by widening
+    /// each round recomputes every fact once without widening,
+    /// starting from a solution, every round is still a solution
+    fn narrow(
+        &self,
+        cfg: &Cfg,
+        result: DataflowResult<Self::Fact>,
+        rounds: usize,
+    ) -> DataflowResult<Self::Fact> {
+        let forward = self.direction() == Direction::Forward;
+        let start = if forward { cfg.entry } else { cfg.exit };
+        let (mut input, mut output) = if forward {
+            (result.before, result.after)
+        } else {
+            (result.after, result.before)
+        };
+        let mut order: Vec<usize> = (0..cfg.len()).collect();
+        if !forward {
+            order.reverse();
+        }
+        for _ in 0..rounds {
+            for &n in &order {
+                if n != start {
+                    if let Some(joined) = incoming(self, cfg, n, &output) {
+                        input[n] = joined;
+                    }
+                }
+                output[n] = self.transfer(&cfg.nodes[n], &input[n]);
+            }
+        }
+        if forward {
+            DataflowResult {
+                before: input,
+                after: output,
+            }
+        } else {
+            DataflowResult {
+                before: output,
+                after: input,
+            }
+        }
+    }
+}
+
+/// result of an analysis for every function of a program, the key is the function name
+/// solve: the result of one function, given the declarations of the program
+pub fn per_function<R>(
+    program: &AstNode,
+    solve: impl Fn(&Cfg, &HashMap<AstNode, AstNode>) -> R,
+) -> HashMap<String, (Cfg, R)> {
+    let decl = DeclarationAnalysis::work(program);
+    let mut res = HashMap::new();
+    for cfg in Cfg::from_program(program) {
+        let facts = solve(&cfg, &decl);
+        if let AstNodeKind::Function(Function { ref name, .. }) = cfg.function.kind {
+            res.insert(name.clone(), (cfg, facts));
+        }
+    }
+    res
+}
+
+/// join of the facts flowing into `n`, None if nothing flows into `n`
+fn incoming<A: Dataflow + ?Sized>(
+    analysis: &A,
+    cfg: &Cfg,
+    n: usize,
+    output: &[A::Fact],
+) -> Option<A::Fact> {
+    let forward = analysis.direction() == Direction::Forward;
+    let flow_in = if forward { &cfg.pred[n] } else { &cfg.succ[n] };
+    flow_in
+        .iter()
+        .map(|&p| {
+            // the edge in program order
+            let (from, to) = if forward { (p, n) } else { (n, p) };
+            let branch = cfg.branch.get(&(from, to)).cloned();
+            analysis.transfer_edge(&cfg.nodes[from], branch, &output[p])
+        })
+        .fold(None, |acc: Option<A::Fact>, x| match acc {
+            Some(acc) => Some(analysis.join(&acc, &x)),
+            None => Some(x),
+        })
+}
@@ -84,7 +84,7 @@ impl Dfs for DeclarationAnalysis {
 
 #[cfg(test)]
 mod tests {
-    use crate::ast_parser::parse;
+    use crate::ast_parser::{parse, Assign, AstNodeKind, DirectFieldWrite};
     use crate::declaration_analysis::DeclarationAnalysis;
     use crate::dfs::Dfs;
     use std::collections::HashMap;
@@ -93,7 +93,7 @@ mod tests {
     #[test]
     fn test_fib_declar() -> std::io::Result<()> {
         let path = "/home/lyj/TIP/examples/fib.tip";
-        let content = fs::read_to_string(&path)?;
+        let content = fs::read_to_string(path)?;
         let program = parse(&content);
         let mut declaration_analysis = DeclarationAnalysis {
             decl: HashMap::new(),
@@ -103,4 +103,30 @@ mod tests {
         dbg!(declaration_analysis.decl);
         Ok(())
     }
+
+    #[test]
+    fn test_field_write_declar() {
+        // x.a = 2 is parsed as an assignment to x, so its target is made by hand
+        let mut program = parse("f() { var x; x = {a: 1}; x = 2; return x.a; }");
+        if let AstNodeKind::Program(ref mut functions) = program.kind {
+            if let AstNodeKind::Function(ref mut function) = functions[0].kind {
+                if let AstNodeKind::Assign(Assign { ref mut left, .. }) =
+                    function.statements[1].kind
+                {
+                    let id = left.clone();
+                    left.kind = AstNodeKind::DirectFieldWrite(DirectFieldWrite {
+                        id,
+                        field: "a".to_string(),
+                    });
+                }
+            }
+        }
+        let decl = DeclarationAnalysis::work(&program);
+        let uses = decl
+            .keys()
+            .filter(|node| matches!(node.kind, AstNodeKind::Id(ref name) if name == "x"))
+            .count();
+        // the x of x.a = 2 is a use too
+        assert_eq!(uses, 3);
+    }
 }
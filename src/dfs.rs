@@ -10,7 +10,9 @@ pub trait Dfs {
         }
         match node.kind {
             AstNodeKind::Id(_) => {}
-            AstNodeKind::DirectFieldWrite(_) => {}
+            AstNodeKind::DirectFieldWrite(DirectFieldWrite { ref id, .. }) => {
+                self.dfs(id);
+            }
             AstNodeKind::IndirectFieldWrite(IndirectFieldWrite { ref expr, .. }) => {
                 self.dfs(expr);
             }
@@ -54,8 +56,6 @@ pub trait Dfs {
                 }
             }
             AstNodeKind::Function(Function {
-                ref params,
-                ref vars,
                 ref statements,
                 ref ret,
                 ..
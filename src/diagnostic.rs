@@ -0,0 +1,44 @@
+use crate::ast_parser::AstNode;
+use std::fmt;
+
+/// a warning attached to the start position of an AstNode
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(node: &AstNode, message: String) -> Self {
+        Diagnostic {
+            line: node.line,
+            col: node.col,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("{}:{}: {}", self.line, self.col, self.message))
+    }
+}
+
+/// how sure an analysis is that a diagnostic is a real bug
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+pub enum Severity {
+    /// the program fails whenever the node is reached
+    Error,
+    /// the program may fail on some executions
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use crate::andersen::Andersen;
+use crate::ast_parser::*;
+use crate::callgraph::CallGraph;
+use crate::declaration_analysis::DeclarationAnalysis;
+use crate::dfs::Dfs;
+use crate::diagnostic::Diagnostic;
+use crate::points_to::Location;
+use std::collections::HashSet;
+use std::fmt;
+
+/// how a pointer to a param or var may outlive its frame
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+pub enum Escape {
+    /// returned by the function, maybe inside a record
+    Returned,
+    /// stored into a cell outside the frame by the function itself
+    Stored(Location),
+    /// passed to a callee (name) which stores it into a cell outside the frame
+    PassedTo(String, Location),
+}
+
+impl fmt::Display for Escape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Escape::Returned => f.write_str("returned"),
+            Escape::Stored(cell) => f.write_fmt(format_args!("stored into `{}`", cell)),
+            Escape::PassedTo(callee, cell) => f.write_fmt(format_args!(
+                "passed to `{}` which stores it into `{}`",
+                callee, cell
+            )),
+        }
+    }
+}
+
+/// the cells a store writes into
+#[derive(Debug, Clone)]
+enum Store {
+    /// the new cell of an AstNode::Alloc
+    Alloc(AstNode),
+    /// the cells an expression points to, `*e = v` or `(*e).f = v`
+    Deref(AstNode),
+}
+
+/// collect every `&x` and every store into a cell, with the enclosing AstNode::Function
+struct EscapeSites {
+    current: Option<AstNode>,
+    refs: Vec<(AstNode, AstNode)>,
+    /// (function, cells, stored value)
+    stores: Vec<(AstNode, Store, AstNode)>,
+}
+
+impl Dfs for EscapeSites {
+    type ResultType = Self;
+
+    fn new(_: &AstNode) -> Self {
+        Self {
+            current: None,
+            refs: vec![],
+            stores: vec![],
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        match node.kind {
+            AstNodeKind::Function(_) => self.current = Some(node.clone()),
+            AstNodeKind::Ref(_) => {
+                let current = self.current.clone().unwrap();
+                self.refs.push((node.clone(), current));
+            }
+            AstNodeKind::Alloc(Alloc { ref expr }) => {
+                let current = self.current.clone().unwrap();
+                self.stores
+                    .push((current, Store::Alloc(node.clone()), expr.as_ref().clone()));
+            }
+            AstNodeKind::Assign(Assign {
+                ref left,
+                ref right,
+            }) => {
+                let pointer = match left.kind {
+                    AstNodeKind::DerefWrite(DerefWrite { ref expr }) => Some(expr),
+                    AstNodeKind::IndirectFieldWrite(IndirectFieldWrite { ref expr, .. }) => {
+                        match expr.kind {
+                            AstNodeKind::Deref(Deref { ref atom }) => Some(atom),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                if let Some(pointer) = pointer {
+                    let current = self.current.clone().unwrap();
+                    self.stores.push((
+                        current,
+                        Store::Deref(pointer.as_ref().clone()),
+                        right.as_ref().clone(),
+                    ));
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        self
+    }
+}
+
+fn name(function: &AstNode) -> &str {
+    match function.kind {
+        AstNodeKind::Function(Function { ref name, .. }) => name,
+        _ => unreachable!(),
+    }
+}
+
+/// functions `function` may call, directly or not
+fn reachable(graph: &CallGraph, function: &str) -> HashSet<String> {
+    let mut res = HashSet::new();
+    let mut stack = graph.callees(function);
+    while let Some(f) = stack.pop() {
+        if res.insert(f.clone()) {
+            stack.extend(graph.callees(&f));
+        }
+    }
+    res
+}
+
+/// every `&x` whose target may outlive its frame, in program order, with how it escapes
+/// a pointer stored into a var of the same frame does not escape,
+/// unless that var escapes itself
+pub fn escapes(program: &AstNode) -> Vec<(AstNode, Vec<Escape>)> {
+    let andersen = Andersen::new(program);
+    let decl = DeclarationAnalysis::work(program);
+    let graph = CallGraph::new(program);
+    let sites = EscapeSites::work(program);
+    let mut res = vec![];
+    for (r, f) in &sites.refs {
+        let x = match r.kind {
+            AstNodeKind::Ref(Ref { ref id }) => match decl.get(id) {
+                Some(x) if !matches!(x.kind, AstNodeKind::Function(_)) => x,
+                _ => continue,
+            },
+            _ => unreachable!(),
+        };
+        let (frame, ret): (HashSet<Location>, &AstNode) = match f.kind {
+            AstNodeKind::Function(Function {
+                ref params,
+                ref vars,
+                ref ret,
+                ..
+            }) => (
+                params
+                    .iter()
+                    .chain(vars)
+                    .cloned()
+                    .map(Location::Var)
+                    .collect(),
+                ret,
+            ),
+            _ => unreachable!(),
+        };
+        let loc = Location::Var(x.clone());
+        let callees = reachable(&graph, name(f));
+        let mut paths = vec![];
+        if andersen.expression(ret).contains(&loc) {
+            paths.push(Escape::Returned);
+        }
+        for (g, store, value) in &sites.stores {
+            if !andersen.expression(value).contains(&loc) {
+                continue;
+            }
+            // a caller which stores the returned pointer is reported as Returned
+            if g != f && !callees.contains(name(g)) {
+                continue;
+            }
+            let cells = match store {
+                Store::Alloc(alloc) => vec![Location::Alloc(alloc.clone())].into_iter().collect(),
+                Store::Deref(pointer) => andersen.expression(pointer),
+            };
+            for cell in cells {
+                if frame.contains(&cell) {
+                    continue;
+                }
+                let path = if g == f {
+                    Escape::Stored(cell)
+                } else {
+                    Escape::PassedTo(name(g).to_string(), cell)
+                };
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        if !paths.is_empty() {
+            res.push((r.clone(), paths));
+        }
+    }
+    res
+}
+
+/// report every escaping `&x`
+pub fn escaping_refs(program: &AstNode) -> Vec<Diagnostic> {
+    escapes(program)
+        .iter()
+        .map(|(r, paths)| {
+            let paths: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+            Diagnostic::new(
+                r,
+                format!("`{}` may outlive its frame: {}", r, paths.join("; ")),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::parse;
+    use crate::escape_analysis::escaping_refs;
+
+    #[test]
+    fn test_escaping_refs() {
+        let program = parse(
+            "keep(v) { var c; c = alloc v; return 0; }
+             f(n) { var x, y, z, c; c = alloc null; *c = &x; z = keep(&n); y = &z; return &y; }
+             main() { var a, b; a = f(1); b = &a; return *b; }",
+        );
+        let res: Vec<String> = escaping_refs(&program)
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        // &z is only stored into y, &a never leaves main
+        assert_eq!(
+            res,
+            vec![
+                "2:58: `&x` may outlive its frame: stored into `alloc-2:41`",
+                "2:71: `&n` may outlive its frame: passed to `keep` which stores it into `alloc-1:22`",
+                "2:91: `&y` may outlive its frame: returned",
+            ]
+        );
+    }
+}
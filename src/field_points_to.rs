@@ -0,0 +1,317 @@
+use crate::ast_parser::*;
+use crate::control_flow_analysis::ControlFlowAnalysis;
+use crate::cubic_solver::CubicSolver;
+use crate::declaration_analysis::DeclarationAnalysis;
+use crate::dfs::Dfs;
+use crate::points_to::{locations, Location, PointsTo};
+use std::collections::{HashMap, HashSet};
+
+/// None: the value itself, Some(f): field f of a record value
+type Field = Option<String>;
+
+/// constraint variable of the field-sensitive analysis
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+enum Node {
+    /// content of an abstract location
+    Cell(Location, Field),
+    /// value of an expression
+    Expr(AstNode, Field),
+    /// returned value of an AstNode::Function
+    Ret(AstNode, Field),
+}
+
+/// every field name of a program, read, written or in a record
+struct FieldNames {
+    fields: HashSet<String>,
+}
+
+impl Dfs for FieldNames {
+    type ResultType = HashSet<String>;
+
+    fn new(_: &AstNode) -> Self {
+        Self {
+            fields: HashSet::new(),
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        match node.kind {
+            AstNodeKind::Record(ref fields) => {
+                self.fields.extend(fields.iter().map(|f| f.name.clone()));
+            }
+            AstNodeKind::FieldAccess(FieldAccess { ref path, .. }) => {
+                self.fields.insert(path.clone());
+            }
+            AstNodeKind::DirectFieldWrite(DirectFieldWrite { ref field, .. })
+            | AstNodeKind::IndirectFieldWrite(IndirectFieldWrite { ref field, .. }) => {
+                self.fields.insert(field.clone());
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        self.fields
+    }
+}
+
+/// Andersen's constraints, where the content of a location is split in one cell per field
+/// abstract locations are (variable or alloc site, field)
+struct FieldConstraints {
+    solver: CubicSolver<Location, Node>,
+    // generate from DeclarationAnalysis
+    decl: HashMap<AstNode, AstNode>,
+    locations: Vec<Location>,
+    /// None and every field name
+    fields: Vec<Field>,
+    /// resolve the calls through function values
+    cfa: ControlFlowAnalysis,
+}
+
+impl FieldConstraints {
+    fn node(&self, node: &AstNode, field: &Field) -> Node {
+        match self.decl.get(node) {
+            Some(x) => Node::Cell(Location::Var(x.clone()), field.clone()),
+            None => Node::Expr(node.clone(), field.clone()),
+        }
+    }
+
+    /// ⟦from⟧ ⊆ ⟦to⟧, field by field
+    fn copy(&mut self, from: &AstNode, to: impl Fn(&Field) -> Node) {
+        for field in self.fields.clone() {
+            self.solver
+                .add_subset(&self.node(from, &field), &to(&field));
+        }
+    }
+
+    /// ⟦*pointer⟧ ⊆ ⟦to⟧, field by field
+    fn load(&mut self, pointer: &AstNode, to: &AstNode) {
+        let p = self.node(pointer, &None);
+        for field in self.fields.clone() {
+            let to = self.node(to, &field);
+            for c in self.locations.clone() {
+                let from = Node::Cell(c.clone(), field.clone());
+                self.solver.add_conditional(&c, &p, &from, &to);
+            }
+        }
+    }
+
+    /// ⟦from⟧ ⊆ ⟦(*pointer).field⟧, or ⟦from⟧ ⊆ ⟦*pointer⟧ field by field if field is None
+    fn store(&mut self, pointer: &AstNode, field: &Field, from: &AstNode) {
+        let p = self.node(pointer, &None);
+        let pairs: Vec<(Field, Field)> = match field {
+            Some(_) => vec![(None, field.clone())],
+            None => self.fields.iter().map(|f| (f.clone(), f.clone())).collect(),
+        };
+        for (from_field, to_field) in pairs {
+            let from = self.node(from, &from_field);
+            for c in self.locations.clone() {
+                let to = Node::Cell(c.clone(), to_field.clone());
+                self.solver.add_conditional(&c, &p, &from, &to);
+            }
+        }
+    }
+}
+
+impl Dfs for FieldConstraints {
+    type ResultType = FieldPointsTo;
+
+    fn new(node: &AstNode) -> Self {
+        let mut fields: Vec<Field> = FieldNames::work(node).into_iter().map(Some).collect();
+        fields.push(None);
+        Self {
+            solver: CubicSolver::new(),
+            decl: DeclarationAnalysis::work(node),
+            locations: locations(node),
+            fields,
+            cfa: ControlFlowAnalysis::new(node),
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        match node.kind {
+            // &x
+            AstNodeKind::Ref(Ref { ref id }) => {
+                if let Some(x) = self.decl.get(id) {
+                    self.solver
+                        .add_constant(&Location::Var(x.clone()), &self.node(node, &None));
+                }
+            }
+            // alloc e: the cell holds every field of e
+            AstNodeKind::Alloc(Alloc { ref expr }) => {
+                let cell = Location::Alloc(node.clone());
+                self.solver.add_constant(&cell, &self.node(node, &None));
+                self.copy(expr, |f| Node::Cell(cell.clone(), f.clone()));
+            }
+            // *e
+            AstNodeKind::Deref(Deref { ref atom }) => self.load(atom, node),
+            // {f: e}
+            AstNodeKind::Record(ref fields) => {
+                for field in fields {
+                    self.solver.add_subset(
+                        &self.node(&field.expression, &None),
+                        &self.node(node, &Some(field.name.clone())),
+                    );
+                }
+            }
+            // e.f
+            AstNodeKind::FieldAccess(FieldAccess { ref name, ref path }) => {
+                self.solver.add_subset(
+                    &self.node(name, &Some(path.clone())),
+                    &self.node(node, &None),
+                );
+            }
+            AstNodeKind::Assign(Assign {
+                ref left,
+                ref right,
+            }) => match left.kind {
+                AstNodeKind::Id(_) => {
+                    let to: HashMap<Field, Node> = self
+                        .fields
+                        .iter()
+                        .map(|f| (f.clone(), self.node(left, f)))
+                        .collect();
+                    self.copy(right, |f| to[f].clone());
+                }
+                // x.f = e
+                AstNodeKind::DirectFieldWrite(DirectFieldWrite { ref id, ref field }) => {
+                    self.solver.add_subset(
+                        &self.node(right, &None),
+                        &self.node(id, &Some(field.clone())),
+                    );
+                }
+                // *e = f
+                AstNodeKind::DerefWrite(DerefWrite { ref expr }) => self.store(expr, &None, right),
+                // (*e).f = g
+                AstNodeKind::IndirectFieldWrite(IndirectFieldWrite {
+                    ref expr,
+                    ref field,
+                }) => match expr.kind {
+                    AstNodeKind::Deref(Deref { ref atom }) => {
+                        self.store(atom, &Some(field.clone()), right)
+                    }
+                    // (x).f = g is x.f = g
+                    AstNodeKind::Id(_) => self.solver.add_subset(
+                        &self.node(right, &None),
+                        &self.node(expr, &Some(field.clone())),
+                    ),
+                    // records are values, any other expression gives a temporary record
+                    // which is dropped after the write, so no location is changed
+                    _ => {}
+                },
+                _ => unreachable!(),
+            },
+            AstNodeKind::FunApp(FunApp { ref params, .. }) => {
+                for f in self.cfa.callees(node) {
+                    if let AstNodeKind::Function(Function {
+                        params: ref formals,
+                        ..
+                    }) = f.kind
+                    {
+                        for (arg, param) in params.iter().zip(formals) {
+                            let param = Location::Var(param.clone());
+                            self.copy(arg, |f| Node::Cell(param.clone(), f.clone()));
+                        }
+                    }
+                    for field in self.fields.clone() {
+                        self.solver.add_subset(
+                            &Node::Ret(f.clone(), field.clone()),
+                            &self.node(node, &field),
+                        );
+                    }
+                }
+            }
+            AstNodeKind::Function(Function { ref ret, .. }) => {
+                self.copy(ret, |f| Node::Ret(node.clone(), f.clone()));
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        let cells = self
+            .solver
+            .solution()
+            .into_iter()
+            .filter_map(|(k, v)| match k {
+                Node::Cell(c, f) => Some(((c, f), v)),
+                _ => None,
+            })
+            .collect();
+        FieldPointsTo { cells }
+    }
+}
+
+/// field-sensitive inclusion-based pointer analysis
+/// a location holding a record has one cell for each field
+pub struct FieldPointsTo {
+    /// (location, field) => locations its content may point to
+    cells: HashMap<(Location, Field), HashSet<Location>>,
+}
+
+impl FieldPointsTo {
+    pub fn new(program: &AstNode) -> Self {
+        FieldConstraints::work(program)
+    }
+
+    /// locations field `field` of the record held by `loc` may point to
+    pub fn field_targets(&self, loc: &Location, field: &str) -> HashSet<Location> {
+        self.cells
+            .get(&(loc.clone(), Some(field.to_string())))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+impl PointsTo for FieldPointsTo {
+    fn targets(&self, loc: &Location) -> HashSet<Location> {
+        self.cells
+            .get(&(loc.clone(), None))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::andersen::Andersen;
+    use crate::ast_parser::*;
+    use crate::field_points_to::FieldPointsTo;
+    use crate::points_to::{names, var, Location, PointsTo};
+
+    #[test]
+    fn test_field_points_to() {
+        let program = parse(
+            "main() { var a, b, r, s, t, x, y; r = alloc {f: &a, g: null}; (*r).g = &b;
+             s = {f: &b, g: &a}; x = (*r).f; y = s.g; t = {f: null, g: null}; (t).g = &b;
+             return 0; }",
+        );
+        let fields = FieldPointsTo::new(&program);
+        let cell = fields
+            .points_to(&var(&program, "r"))
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(names(fields.field_targets(&cell, "f")), vec!["a"]);
+        assert_eq!(names(fields.field_targets(&cell, "g")), vec!["b"]);
+        assert_eq!(names(fields.points_to(&var(&program, "x"))), vec!["a"]);
+        assert_eq!(names(fields.points_to(&var(&program, "y"))), vec!["a"]);
+        assert_eq!(
+            names(fields.field_targets(&Location::Var(var(&program, "s")), "f")),
+            vec!["b"]
+        );
+        assert_eq!(
+            names(fields.field_targets(&Location::Var(var(&program, "t")), "g")),
+            vec!["b"]
+        );
+
+        // the fields of the cell are merged without field sensitivity
+        let andersen = Andersen::new(&program);
+        assert_eq!(
+            names(andersen.points_to(&var(&program, "x"))),
+            vec!["a", "b"]
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use crate::andersen::Andersen;
+use crate::ast_parser::*;
+use crate::available_expressions::contains_call;
+use crate::callgraph::CallGraph;
+use crate::cfg::{address_taken, exprs, Cfg, CfgNode};
+use crate::dataflow::{per_function, Dataflow, DataflowResult, Direction};
+use crate::points_to::{allocs, Location, PointsTo};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// points-to graph at a program point
+/// a location without target has no entry, so equal graphs are equal maps
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PointsToGraph {
+    edges: HashMap<Location, HashSet<Location>>,
+}
+
+impl PointsToGraph {
+    /// strong update: the old targets are lost
+    fn set(&mut self, loc: Location, targets: HashSet<Location>) {
+        if targets.is_empty() {
+            self.edges.remove(&loc);
+        } else {
+            self.edges.insert(loc, targets);
+        }
+    }
+
+    /// weak update: the old targets survive
+    fn add(&mut self, loc: Location, targets: HashSet<Location>) {
+        if !targets.is_empty() {
+            self.edges.entry(loc).or_default().extend(targets);
+        }
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        let mut res = self.clone();
+        for (loc, targets) in &other.edges {
+            res.add(loc.clone(), targets.clone());
+        }
+        res
+    }
+}
+
+impl PointsTo for PointsToGraph {
+    fn targets(&self, loc: &Location) -> HashSet<Location> {
+        self.edges.get(loc).cloned().unwrap_or_default()
+    }
+}
+
+/// one `x -> a, b` line per location, sorted
+impl fmt::Display for PointsToGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines: Vec<String> = self
+            .edges
+            .iter()
+            .map(|(loc, targets)| {
+                let mut targets: Vec<String> = targets.iter().map(|l| l.to_string()).collect();
+                targets.sort();
+                format!("{} -> {}", loc, targets.join(", "))
+            })
+            .collect();
+        lines.sort();
+        for line in lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// forward may analysis of the points-to graph of a function
+/// only the params, vars and alloc sites of the function are tracked,
+/// the other locations and the returned values of calls come from Andersen
+pub struct FlowPointsTo<'a> {
+    andersen: &'a Andersen,
+    // generate from DeclarationAnalysis
+    decl: &'a HashMap<AstNode, AstNode>,
+    params: Vec<AstNode>,
+    /// params, vars and alloc sites of the function
+    tracked: HashSet<Location>,
+    /// a location standing for several cells is never strongly updated:
+    /// alloc sites inside a loop, every location of a recursive function
+    summary: HashSet<Location>,
+    /// locations a callee may overwrite: vars whose address is taken, alloc sites
+    escaped: HashSet<Location>,
+}
+
+impl<'a> FlowPointsTo<'a> {
+    pub fn new(
+        cfg: &Cfg,
+        andersen: &'a Andersen,
+        decl: &'a HashMap<AstNode, AstNode>,
+        graph: &CallGraph,
+    ) -> Self {
+        let (name, params, vars) = match cfg.function.kind {
+            AstNodeKind::Function(Function {
+                ref name,
+                ref params,
+                ref vars,
+                ..
+            }) => (name, params, vars),
+            _ => unreachable!(),
+        };
+        let cells: HashSet<Location> = allocs(&cfg.function)
+            .into_iter()
+            .map(Location::Alloc)
+            .collect();
+        let mut tracked: HashSet<Location> = params
+            .iter()
+            .chain(vars)
+            .cloned()
+            .map(Location::Var)
+            .collect();
+        tracked.extend(cells.iter().cloned());
+        let summary = if graph.is_recursive(name) {
+            tracked.clone()
+        } else {
+            cfg.nodes
+                .iter()
+                .filter_map(|node| match node {
+                    CfgNode::Guard(stmt) if matches!(stmt.kind, AstNodeKind::While(_)) => {
+                        Some(allocs(stmt))
+                    }
+                    _ => None,
+                })
+                .flatten()
+                .map(Location::Alloc)
+                .collect()
+        };
+        let mut escaped: HashSet<Location> = address_taken(&cfg.function, decl)
+            .into_iter()
+            .map(Location::Var)
+            .collect();
+        escaped.extend(cells);
+        Self {
+            andersen,
+            decl,
+            params: params.clone(),
+            tracked,
+            summary,
+            escaped,
+        }
+    }
+
+    /// targets of the content of a location
+    fn load(&self, loc: &Location, graph: &PointsToGraph) -> HashSet<Location> {
+        if self.tracked.contains(loc) {
+            graph.targets(loc)
+        } else {
+            self.andersen.targets(loc)
+        }
+    }
+
+    /// locations the value of an expression may point to
+    pub fn eval(&self, expr: &AstNode, graph: &PointsToGraph) -> HashSet<Location> {
+        match expr.kind {
+            AstNodeKind::Id(_) => match self.decl.get(expr) {
+                Some(x) if !matches!(x.kind, AstNodeKind::Function(_)) => {
+                    self.load(&Location::Var(x.clone()), graph)
+                }
+                _ => HashSet::new(),
+            },
+            AstNodeKind::Ref(Ref { ref id }) => self
+                .decl
+                .get(id)
+                .map(|x| Location::Var(x.clone()))
+                .into_iter()
+                .collect(),
+            AstNodeKind::Alloc(_) => vec![Location::Alloc(expr.clone())].into_iter().collect(),
+            AstNodeKind::Deref(Deref { ref atom }) => self
+                .eval(atom, graph)
+                .iter()
+                .flat_map(|loc| self.load(loc, graph))
+                .collect(),
+            // field-insensitive: a record points to the targets of all its fields
+            AstNodeKind::Record(ref fields) => fields
+                .iter()
+                .flat_map(|field| self.eval(&field.expression, graph))
+                .collect(),
+            AstNodeKind::FieldAccess(FieldAccess { ref name, .. }) => self.eval(name, graph),
+            AstNodeKind::FunApp(_) => self.andersen.expression(expr),
+            _ => HashSet::new(),
+        }
+    }
+
+    /// local variable of an Id
+    fn local(&self, id: &AstNode) -> Option<Location> {
+        self.decl
+            .get(id)
+            .map(|x| Location::Var(x.clone()))
+            .filter(|loc| self.tracked.contains(loc))
+    }
+}
+
+impl<'a> Dataflow for FlowPointsTo<'a> {
+    type Fact = PointsToGraph;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    /// params may point to whatever any caller passes, vars point to nothing
+    fn boundary(&self, _: &Cfg) -> Self::Fact {
+        let mut res = PointsToGraph::default();
+        for param in &self.params {
+            let loc = Location::Var(param.clone());
+            res.add(loc.clone(), self.andersen.targets(&loc));
+        }
+        res
+    }
+
+    fn init(&self, _: &Cfg) -> Self::Fact {
+        PointsToGraph::default()
+    }
+
+    fn join(&self, a: &Self::Fact, b: &Self::Fact) -> Self::Fact {
+        a.join(b)
+    }
+
+    fn transfer(&self, node: &CfgNode, fact: &Self::Fact) -> Self::Fact {
+        let mut res = fact.clone();
+        // a new cell holds the value of the initializer
+        for expr in exprs(node) {
+            for alloc in allocs(expr) {
+                let v = match alloc.kind {
+                    AstNodeKind::Alloc(Alloc { ref expr }) => self.eval(expr, fact),
+                    _ => unreachable!(),
+                };
+                let loc = Location::Alloc(alloc);
+                if self.summary.contains(&loc) {
+                    res.add(loc, v);
+                } else {
+                    res.set(loc, v);
+                }
+            }
+        }
+        if exprs(node).iter().any(|e| contains_call(e)) {
+            for loc in &self.escaped {
+                res.add(loc.clone(), self.andersen.targets(loc));
+            }
+        }
+        if let CfgNode::Stmt(AstNode {
+            kind:
+                AstNodeKind::Assign(Assign {
+                    ref left,
+                    ref right,
+                }),
+            ..
+        }) = node
+        {
+            let state = res.clone();
+            let v = self.eval(right, &state);
+            match left.kind {
+                AstNodeKind::Id(_) => {
+                    if let Some(x) = self.local(left) {
+                        res.set(x, v);
+                    }
+                }
+                // field-insensitive: the other fields survive
+                AstNodeKind::DirectFieldWrite(DirectFieldWrite { id: ref x, .. }) => {
+                    if let Some(x) = self.local(x) {
+                        res.add(x, v);
+                    }
+                }
+                AstNodeKind::IndirectFieldWrite(IndirectFieldWrite { ref expr, .. }) => {
+                    match expr.kind {
+                        AstNodeKind::Deref(Deref { ref atom }) => {
+                            for loc in self.eval(atom, &state) {
+                                if self.tracked.contains(&loc) {
+                                    res.add(loc, v.clone());
+                                }
+                            }
+                        }
+                        _ => {
+                            if let Some(x) = self.local(expr) {
+                                res.add(x, v);
+                            }
+                        }
+                    }
+                }
+                // *e = f: strong update if e points to exactly one concrete cell
+                AstNodeKind::DerefWrite(DerefWrite { ref expr }) => {
+                    let targets = self.eval(expr, &state);
+                    let strong = targets.len() == 1 && targets.is_disjoint(&self.summary);
+                    for loc in targets {
+                        if !self.tracked.contains(&loc) {
+                            continue;
+                        }
+                        if strong {
+                            res.set(loc, v.clone());
+                        } else {
+                            res.add(loc, v.clone());
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+        res
+    }
+}
+
+/// points-to graphs of every function, the key is the function name
+pub fn flow_points_to(program: &AstNode) -> HashMap<String, (Cfg, DataflowResult<PointsToGraph>)> {
+    let andersen = Andersen::new(program);
+    let graph = CallGraph::new(program);
+    per_function(program, |cfg, decl| {
+        FlowPointsTo::new(cfg, &andersen, decl, &graph).solve(cfg)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::andersen::Andersen;
+    use crate::ast_parser::*;
+    use crate::cfg::CfgNode;
+    use crate::flow_points_to::flow_points_to;
+    use crate::points_to::{names, var, PointsTo};
+
+    #[test]
+    fn test_flow_points_to() {
+        let program = parse(
+            "main() { var a, b, p, q, r, i; p = &a; q = &p; *q = &b; i = 3;
+             while (i > 0) { r = alloc null; *r = &a; *r = &b; i = i - 1; }
+             r = alloc null; *r = &a; *r = &b; return 0; }",
+        );
+        let p = var(&program, "p");
+        let res = flow_points_to(&program);
+        let (cfg, graphs) = &res["main"];
+        let ret = cfg
+            .nodes
+            .iter()
+            .position(|n| matches!(n, CfgNode::Return(_)))
+            .unwrap();
+        // *q = &b overwrites p, the only target of q
+        assert_eq!(names(graphs.before[ret].points_to(&p)), vec!["b"]);
+        // the alloc in the loop is a summary node, the other one is updated strongly
+        assert_eq!(
+            graphs.before[ret].to_string(),
+            "alloc-2:34 -> a, b\nalloc-3:18 -> b\np -> b\nq -> p\nr -> alloc-3:18\n"
+        );
+        assert_eq!(names(Andersen::new(&program).points_to(&p)), vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::ast_parser::*;
+use crate::cfg::{def, exprs, ids, Cfg, CfgNode};
+use crate::dataflow::{Dataflow, Direction};
+use crate::declaration_analysis::DeclarationAnalysis;
+use crate::dfs::Dfs;
+use crate::diagnostic::Diagnostic;
+use std::collections::{HashMap, HashSet};
+
+/// forward must analysis
+/// a fact is the set of definitely initialized params and vars (declaration)
+/// a write through a pointer never initializes a variable, even if its address is taken:
+/// `*p = e` may write to x or not, so a later read of x is still reported
+pub struct InitializedVariables<'a> {
+    // generate from DeclarationAnalysis
+    decl: &'a HashMap<AstNode, AstNode>,
+    params: HashSet<AstNode>,
+    vars: HashSet<AstNode>,
+}
+
+impl<'a> InitializedVariables<'a> {
+    pub fn new(cfg: &Cfg, decl: &'a HashMap<AstNode, AstNode>) -> Self {
+        if let AstNodeKind::Function(Function {
+            ref params,
+            ref vars,
+            ..
+        }) = cfg.function.kind
+        {
+            Self {
+                decl,
+                params: params.iter().cloned().collect(),
+                vars: vars.iter().cloned().collect(),
+            }
+        } else {
+            unreachable!();
+        }
+    }
+}
+
+impl<'a> Dataflow for InitializedVariables<'a> {
+    type Fact = HashSet<AstNode>;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    /// parameters are bound by the caller
+    fn boundary(&self, _: &Cfg) -> Self::Fact {
+        self.params.clone()
+    }
+
+    fn init(&self, _: &Cfg) -> Self::Fact {
+        self.params.union(&self.vars).cloned().collect()
+    }
+
+    fn join(&self, a: &Self::Fact, b: &Self::Fact) -> Self::Fact {
+        a.intersection(b).cloned().collect()
+    }
+
+    fn transfer(&self, node: &CfgNode, fact: &Self::Fact) -> Self::Fact {
+        let mut res = fact.clone();
+        if let Some(x) = def(node, self.decl) {
+            res.insert(x);
+        }
+        res
+    }
+}
+
+/// report every read of a var which may not be initialized yet
+pub fn uninitialized_reads(program: &AstNode) -> Vec<Diagnostic> {
+    let decl = DeclarationAnalysis::work(program);
+    let mut res = vec![];
+    for cfg in Cfg::from_program(program) {
+        let analysis = InitializedVariables::new(&cfg, &decl);
+        let initialized = analysis.solve(&cfg);
+        for (i, node) in cfg.nodes.iter().enumerate() {
+            for id in exprs(node).into_iter().flat_map(ids) {
+                let x = match decl.get(&id) {
+                    Some(x) if analysis.vars.contains(x) => x,
+                    _ => continue,
+                };
+                if !initialized.before[i].contains(x) {
+                    if let AstNodeKind::Id(ref name) = id.kind {
+                        res.push(Diagnostic::new(
+                            &id,
+                            format!("`{}` may be read before it is initialized", name),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::parse;
+    use crate::initialized_variables::uninitialized_reads;
+
+    #[test]
+    fn test_uninitialized_reads() {
+        let program = parse(
+            "f(n) { var x, y, p; if (n > 0) { x = 1; } y = n; p = &y; *p = x; return x + y; }",
+        );
+        let warnings: Vec<String> = uninitialized_reads(&program)
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "1:63: `x` may be read before it is initialized",
+                "1:73: `x` may be read before it is initialized",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_uninitialized_loop() {
+        // x is initialized on every path reaching the read
+        let program =
+            parse("main() { var x, i; i = input; x = 0; while (i > 0) { i = i - x; } return x; }");
+        assert!(uninitialized_reads(&program).is_empty());
+    }
+}
@@ -0,0 +1,410 @@
+use crate::ast_parser::*;
+use crate::cfg::Cfg;
+use crate::dataflow::{per_function, Dataflow, DataflowResult};
+use crate::value_analysis::{ValueAnalysis, ValueLattice, ValueState, Widening};
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::fmt;
+
+/// bound of an interval
+/// the declaration order gives NegInf < Int(_) < PosInf
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Bound {
+    NegInf,
+    Int(i64),
+    PosInf,
+}
+
+impl Bound {
+    /// finite bounds out of i32 become infinite
+    fn clamp(self) -> Self {
+        match self {
+            Bound::Int(n) if n > i32::MAX as i64 => Bound::PosInf,
+            Bound::Int(n) if n < i32::MIN as i64 => Bound::NegInf,
+            _ => self,
+        }
+    }
+
+    /// a finite bound out of i32, infinite bounds never overflow like in the TIP book
+    fn overflows(self) -> bool {
+        self != self.clamp()
+    }
+
+    fn neg(self) -> Self {
+        match self {
+            Bound::NegInf => Bound::PosInf,
+            Bound::Int(n) => Bound::Int(-n),
+            Bound::PosInf => Bound::NegInf,
+        }
+    }
+
+    /// -inf + +inf never happens for bounds of non empty intervals
+    fn add(self, other: Self) -> Self {
+        match (self, other) {
+            (Bound::Int(a), Bound::Int(b)) => Bound::Int(a + b),
+            (Bound::NegInf, _) | (_, Bound::NegInf) => Bound::NegInf,
+            _ => Bound::PosInf,
+        }
+    }
+
+    fn mul(self, other: Self) -> Self {
+        match (self, other) {
+            (Bound::Int(a), Bound::Int(b)) => Bound::Int(a * b),
+            (Bound::Int(0), _) | (_, Bound::Int(0)) => Bound::Int(0),
+            (a, b) => {
+                if (a > Bound::Int(0)) == (b > Bound::Int(0)) {
+                    Bound::PosInf
+                } else {
+                    Bound::NegInf
+                }
+            }
+        }
+    }
+
+    /// other is never 0
+    fn div(self, other: Self) -> Self {
+        match (self, other) {
+            (Bound::Int(a), Bound::Int(b)) => Bound::Int(a / b),
+            (Bound::Int(_), _) => Bound::Int(0),
+            (a, b) => {
+                if (a > Bound::Int(0)) == (b > Bound::Int(0)) {
+                    Bound::PosInf
+                } else {
+                    Bound::NegInf
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bound::NegInf => f.write_str("-inf"),
+            Bound::Int(n) => f.write_fmt(format_args!("{}", n)),
+            Bound::PosInf => f.write_str("+inf"),
+        }
+    }
+}
+
+/// [l, h] with l <= h
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Interval {
+    Bot,
+    Range(Bound, Bound),
+}
+
+impl Interval {
+    pub fn new(l: i64, h: i64) -> Self {
+        Interval::Range(Bound::Int(l), Bound::Int(h))
+    }
+
+    fn range(l: Bound, h: Bound) -> Self {
+        let (l, h) = (l.clamp(), h.clamp());
+        if l > h || l == Bound::PosInf || h == Bound::NegInf {
+            Interval::Bot
+        } else {
+            Interval::Range(l, h)
+        }
+    }
+
+    /// the result of an arithmetic operation
+    /// i32 arithmetic wraps around, so an overflowing bound may give any value
+    fn wrapping(l: Bound, h: Bound) -> Self {
+        if l.overflows() || h.overflows() {
+            Interval::top()
+        } else {
+            Interval::range(l, h)
+        }
+    }
+
+    /// smallest interval containing every corner
+    fn hull(corners: [Bound; 4]) -> Self {
+        let l = corners.iter().min().unwrap();
+        let h = corners.iter().max().unwrap();
+        Interval::wrapping(*l, *h)
+    }
+}
+
+impl ValueLattice for Interval {
+    fn top() -> Self {
+        Interval::Range(Bound::NegInf, Bound::PosInf)
+    }
+
+    fn bot() -> Self {
+        Interval::Bot
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Interval::Bot, x) | (x, Interval::Bot) => *x,
+            (Interval::Range(l1, h1), Interval::Range(l2, h2)) => {
+                Interval::Range(min(*l1, *l2), max(*h1, *h2))
+            }
+        }
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Interval::Range(l1, h1), Interval::Range(l2, h2)) => {
+                let (l, h) = (max(*l1, *l2), min(*h1, *h2));
+                if l <= h {
+                    Interval::Range(l, h)
+                } else {
+                    Interval::Bot
+                }
+            }
+            _ => Interval::Bot,
+        }
+    }
+
+    fn constant(n: i32) -> Self {
+        Interval::new(n as i64, n as i64)
+    }
+
+    fn greater_than(v: &Self) -> Self {
+        match v {
+            Interval::Range(l, _) => Interval::range(l.add(Bound::Int(1)), Bound::PosInf),
+            Interval::Bot => Interval::Bot,
+        }
+    }
+
+    fn less_than(v: &Self) -> Self {
+        match v {
+            Interval::Range(_, h) => Interval::range(Bound::NegInf, h.add(Bound::Int(-1))),
+            Interval::Bot => Interval::Bot,
+        }
+    }
+
+    /// only a bound can be removed
+    fn remove(&self, v: &Self) -> Self {
+        match (self, v) {
+            (Interval::Range(l, h), Interval::Range(c, c2)) if c == c2 => {
+                let l = if l == c { l.add(Bound::Int(1)) } else { *l };
+                let h = if h == c { h.add(Bound::Int(-1)) } else { *h };
+                if l <= h {
+                    Interval::Range(l, h)
+                } else {
+                    Interval::Bot
+                }
+            }
+            _ => *self,
+        }
+    }
+
+    fn binary(op: &Op, left: &Self, right: &Self) -> Self {
+        let (l1, h1, l2, h2) = match (left, right) {
+            (Interval::Range(l1, h1), Interval::Range(l2, h2)) => (*l1, *h1, *l2, *h2),
+            _ => return Interval::Bot,
+        };
+        match op {
+            Op::Add => Interval::wrapping(l1.add(l2), h1.add(h2)),
+            Op::Subtract => Interval::wrapping(l1.add(h2.neg()), h1.add(l2.neg())),
+            Op::Multiply => Interval::hull([l1.mul(l2), l1.mul(h2), h1.mul(l2), h1.mul(h2)]),
+            Op::Divide => {
+                // split the divisor around 0, division by zero has no value
+                let negative = right.meet(&Interval::Range(Bound::NegInf, Bound::Int(-1)));
+                let positive = right.meet(&Interval::Range(Bound::Int(1), Bound::PosInf));
+                [negative, positive]
+                    .iter()
+                    .map(|d| match d {
+                        Interval::Range(l2, h2) => {
+                            Interval::hull([l1.div(*l2), l1.div(*h2), h1.div(*l2), h1.div(*h2)])
+                        }
+                        Interval::Bot => Interval::Bot,
+                    })
+                    .fold(Interval::Bot, |acc, x| acc.join(&x))
+            }
+            Op::Gt => {
+                if l1 > h2 {
+                    Interval::new(1, 1)
+                } else if h1 <= l2 {
+                    Interval::new(0, 0)
+                } else {
+                    Interval::new(0, 1)
+                }
+            }
+            Op::Equal => {
+                if l1 == h1 && l2 == h2 && l1 == l2 {
+                    Interval::new(1, 1)
+                } else if left.meet(right) == Interval::Bot {
+                    Interval::new(0, 0)
+                } else {
+                    Interval::new(0, 1)
+                }
+            }
+        }
+    }
+
+    /// an unstable bound jumps to the closest threshold outside of it
+    fn widen(&self, new: &Self, widening: &Widening) -> Self {
+        match (self, new) {
+            (Interval::Bot, x) | (x, Interval::Bot) => *x,
+            (Interval::Range(l1, h1), Interval::Range(l2, h2)) => {
+                let l = if l2 < l1 {
+                    widening
+                        .thresholds
+                        .iter()
+                        .rev()
+                        .map(|t| Bound::Int(*t as i64))
+                        .find(|t| t <= l2)
+                        .unwrap_or(Bound::NegInf)
+                } else {
+                    *l1
+                };
+                let h = if h2 > h1 {
+                    widening
+                        .thresholds
+                        .iter()
+                        .map(|t| Bound::Int(*t as i64))
+                        .find(|t| t >= h2)
+                        .unwrap_or(Bound::PosInf)
+                } else {
+                    *h1
+                };
+                Interval::Range(l, h)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interval::Bot => f.write_str("⊥"),
+            Interval::Range(l, h) => f.write_fmt(format_args!("[{}, {}]", l, h)),
+        }
+    }
+}
+
+/// intervals of every function, the key is the function name
+/// narrowing: number of narrowing rounds after the widened fixpoint
+pub fn interval_analysis(
+    program: &AstNode,
+    widening: Widening,
+    narrowing: usize,
+) -> HashMap<String, (Cfg, DataflowResult<ValueState<Interval>>)> {
+    per_function(program, |cfg, decl| {
+        let analysis = ValueAnalysis::<Interval>::new(cfg, decl).with_widening(widening.clone());
+        analysis.narrow(cfg, analysis.solve(cfg), narrowing)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::{parse, Op};
+    use crate::interval_analysis::{interval_analysis, Bound, Interval};
+    use crate::value_analysis::{report, ValueLattice, Widening};
+
+    #[test]
+    fn test_interval_arithmetic() {
+        let a = Interval::new(-2, 3);
+        let b = Interval::new(-1, 4);
+        assert_eq!(
+            Interval::binary(&Op::Multiply, &a, &b),
+            Interval::new(-8, 12)
+        );
+        assert_eq!(
+            Interval::binary(&Op::Subtract, &a, &b),
+            Interval::new(-6, 4)
+        );
+        // 0 is removed from the divisor
+        assert_eq!(
+            Interval::binary(&Op::Divide, &Interval::new(6, 6), &b),
+            Interval::new(-6, 6)
+        );
+        assert_eq!(
+            Interval::binary(&Op::Divide, &a, &Interval::new(0, 0)),
+            Interval::Bot
+        );
+        let widening = Widening {
+            thresholds: vec![0, 10].into_iter().collect(),
+        };
+        assert_eq!(
+            Interval::new(0, 1).widen(&Interval::new(0, 2), &widening),
+            Interval::new(0, 10)
+        );
+        assert_eq!(
+            Interval::new(0, 1).widen(&Interval::new(-1, 1), &Widening::new()),
+            Interval::Range(Bound::NegInf, Bound::Int(1))
+        );
+    }
+
+    #[test]
+    fn test_interval_overflow() {
+        let max = Interval::constant(i32::MAX);
+        assert_eq!(
+            Interval::binary(&Op::Add, &max, &Interval::constant(1)),
+            Interval::top()
+        );
+        assert_eq!(
+            Interval::binary(
+                &Op::Divide,
+                &Interval::constant(i32::MIN),
+                &Interval::constant(-1)
+            ),
+            Interval::top()
+        );
+        // y wraps around to a negative value, the branch is still reachable
+        let program = parse(
+            "main() { var x, y; x = 2147483647; y = x + 1; if (y > 0) { y = 1; } return y; }",
+        );
+        let res = interval_analysis(&program, Widening::new(), 0);
+        let (cfg, intervals) = &res["main"];
+        let lines = report(cfg, intervals);
+        assert_eq!(
+            lines[3],
+            "1:60: x = [2147483647, 2147483647], y = [1, +inf]"
+        );
+        assert_eq!(
+            lines[4],
+            "1:76: x = [2147483647, 2147483647], y = [-inf, 1]"
+        );
+        // nothing is greater than i32::MAX, x <= i32::MAX always holds
+        let program = parse(
+            "main() { var x, y; x = input; y = 0;
+             if (x > 2147483647) { y = 1; } else { y = x; } return y; }",
+        );
+        let res = interval_analysis(&program, Widening::new(), 0);
+        let (cfg, intervals) = &res["main"];
+        let lines = report(cfg, intervals);
+        assert_eq!(lines[3], "2:36: x = ⊥, y = ⊥");
+        assert_eq!(lines[4], "2:52: x = [-inf, 2147483647], y = [0, 0]");
+    }
+
+    #[test]
+    fn test_interval_analysis() {
+        let program = parse(
+            "main() { var i, x; i = 0; x = 0; while (input) { i = i + 1; x = 5; } return i; }",
+        );
+        // at the loop head, without thresholds and narrowing
+        let res = interval_analysis(&program, Widening::new(), 0);
+        let (cfg, intervals) = &res["main"];
+        assert_eq!(
+            report(cfg, intervals)[2],
+            "1:34: i = [0, +inf], x = [0, +inf]"
+        );
+
+        // the constants 0, 1 and 5 are thresholds
+        let res = interval_analysis(&program, Widening::from_program(&program), 0);
+        let (cfg, intervals) = &res["main"];
+        assert_eq!(report(cfg, intervals)[2], "1:34: i = [0, +inf], x = [0, 5]");
+
+        // narrowing recovers the bound of x = 5 at the loop head
+        let res = interval_analysis(&program, Widening::new(), 2);
+        let (cfg, intervals) = &res["main"];
+        assert_eq!(report(cfg, intervals)[2], "1:34: i = [0, +inf], x = [0, 5]");
+    }
+
+    #[test]
+    fn test_interval_refinement() {
+        let program = parse("main() { var i; i = 0; while (10 > i) { i = i + 1; } return i; }");
+        let res = interval_analysis(&program, Widening::new(), 1);
+        let (cfg, intervals) = &res["main"];
+        let lines = report(cfg, intervals);
+        assert_eq!(lines[1], "1:24: i = [0, 10]");
+        // loop body
+        assert_eq!(lines[2], "1:41: i = [0, 9]");
+        assert_eq!(lines[3], "1:61: i = [10, 10]");
+    }
+}
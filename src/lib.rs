@@ -6,11 +6,34 @@ extern crate pest_derive;
 #[macro_use]
 extern crate lazy_static;
 
-mod ast_parser;
+pub mod andersen;
+pub mod ast_parser;
+pub mod available_expressions;
+pub mod callgraph;
+pub mod cfg;
+pub mod constant_propagation;
+pub mod control_flow_analysis;
+pub mod cubic_solver;
+pub mod dataflow;
 mod declaration_analysis;
 mod dfs;
-mod field_collector;
-mod sign_lattice;
-mod term;
-mod type_analysis;
+pub mod diagnostic;
+pub mod escape_analysis;
+pub mod field_points_to;
+pub mod flow_points_to;
+pub mod initialized_variables;
+pub mod interval_analysis;
+pub mod liveness;
+pub mod null_analysis;
+pub mod points_to;
+pub mod reaching_definitions;
+pub mod sign_lattice;
+pub mod steensgaard;
+pub mod term;
+pub mod type_analysis;
+pub mod type_equivalence;
+pub mod type_parser;
+pub mod type_printer;
 mod union_find;
+pub mod value_analysis;
+pub mod very_busy_expressions;
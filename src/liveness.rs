@@ -0,0 +1,138 @@
+use crate::ast_parser::*;
+use crate::cfg::{address_taken, def, uses, Cfg, CfgNode};
+use crate::dataflow::{per_function, Dataflow, DataflowResult, Direction};
+use crate::declaration_analysis::DeclarationAnalysis;
+use crate::dfs::Dfs;
+use crate::diagnostic::Diagnostic;
+use std::collections::{HashMap, HashSet};
+
+/// backward may analysis
+/// a fact is the set of live variables (declaration of params and vars)
+pub struct Liveness<'a> {
+    // generate from DeclarationAnalysis
+    decl: &'a HashMap<AstNode, AstNode>,
+    /// `&x` is taken, x may be read through a pointer at any time
+    /// so x is live at exit and never killed
+    escaped: HashSet<AstNode>,
+}
+
+impl<'a> Liveness<'a> {
+    pub fn new(cfg: &Cfg, decl: &'a HashMap<AstNode, AstNode>) -> Self {
+        Self {
+            decl,
+            escaped: address_taken(&cfg.function, decl),
+        }
+    }
+}
+
+impl<'a> Dataflow for Liveness<'a> {
+    type Fact = HashSet<AstNode>;
+
+    fn direction(&self) -> Direction {
+        Direction::Backward
+    }
+
+    fn boundary(&self, _: &Cfg) -> Self::Fact {
+        self.escaped.clone()
+    }
+
+    fn init(&self, _: &Cfg) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn join(&self, a: &Self::Fact, b: &Self::Fact) -> Self::Fact {
+        a.union(b).cloned().collect()
+    }
+
+    fn transfer(&self, node: &CfgNode, fact: &Self::Fact) -> Self::Fact {
+        let mut res = fact.clone();
+        if let Some(x) = def(node, self.decl) {
+            if !self.escaped.contains(&x) {
+                res.remove(&x);
+            }
+        }
+        res.extend(uses(node, self.decl));
+        res
+    }
+}
+
+/// live variables of every function, the key is the function name
+pub fn liveness(program: &AstNode) -> HashMap<String, (Cfg, DataflowResult<HashSet<AstNode>>)> {
+    per_function(program, |cfg, decl| Liveness::new(cfg, decl).solve(cfg))
+}
+
+/// warn about assignments whose target is dead afterwards
+/// and vars which are never live
+pub fn dead_assignments(program: &AstNode) -> Vec<Diagnostic> {
+    let decl = DeclarationAnalysis::work(program);
+    let mut res = vec![];
+    for cfg in Cfg::from_program(program) {
+        let live = Liveness::new(&cfg, &decl).solve(&cfg);
+        for (i, node) in cfg.nodes.iter().enumerate() {
+            if let Some(x) = def(node, &decl) {
+                if !live.after[i].contains(&x) {
+                    if let AstNodeKind::Id(ref name) = x.kind {
+                        res.push(Diagnostic::new(
+                            node.ast().unwrap(),
+                            format!("value assigned to `{}` is never read", name),
+                        ));
+                    }
+                }
+            }
+        }
+        if let AstNodeKind::Function(Function { ref vars, .. }) = cfg.function.kind {
+            for var in vars {
+                let is_live = live
+                    .before
+                    .iter()
+                    .chain(live.after.iter())
+                    .any(|l| l.contains(var));
+                if !is_live {
+                    if let AstNodeKind::Id(ref name) = var.kind {
+                        res.push(Diagnostic::new(
+                            var,
+                            format!("variable `{}` is never live", name),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::parse;
+    use crate::liveness::{dead_assignments, liveness};
+
+    #[test]
+    fn test_liveness() {
+        let source = "main() { var x, y, z; x = input; y = x + 1; z = 7; x = y; return y; }";
+        let program = parse(source);
+        let res = liveness(&program);
+        let (cfg, live) = &res["main"];
+        // x is dead before the last assignment to x
+        assert_eq!(live.before[cfg.find(source, "x = y;")].len(), 1);
+        assert_eq!(live.before[cfg.find(source, "return y;")].len(), 1);
+        let warnings: Vec<String> = dead_assignments(&program)
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "1:45: value assigned to `z` is never read",
+                "1:52: value assigned to `x` is never read",
+                "1:20: variable `z` is never live",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_liveness_ref() {
+        // x can be read through p
+        let program = parse("main() { var x, p; p = &x; x = 1; output *p; return 0; }");
+        assert!(dead_assignments(&program).is_empty());
+    }
+}
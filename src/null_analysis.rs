@@ -0,0 +1,504 @@
+use crate::andersen::Andersen;
+use crate::ast_parser::*;
+use crate::available_expressions::Writes;
+use crate::cfg::{address_taken, exprs, Cfg, CfgNode};
+use crate::dataflow::{per_function, Dataflow, DataflowResult, Direction};
+use crate::declaration_analysis::DeclarationAnalysis;
+use crate::dfs::Dfs;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::points_to::Location;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// whether a value may be null
+/// integers, records and functions are NonNull
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Nullness {
+    /// no value
+    Bot,
+    NonNull,
+    Null,
+    MaybeNull,
+}
+
+impl Nullness {
+    pub fn join(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Nullness::Bot, x) | (x, Nullness::Bot) => *x,
+            (a, b) if a == b => *a,
+            _ => Nullness::MaybeNull,
+        }
+    }
+
+    /// the value is null
+    fn assume_null(&self) -> Self {
+        match self {
+            Nullness::Bot | Nullness::NonNull => Nullness::Bot,
+            _ => Nullness::Null,
+        }
+    }
+
+    /// the value is not null
+    fn assume_non_null(&self) -> Self {
+        match self {
+            Nullness::Bot | Nullness::Null => Nullness::Bot,
+            _ => Nullness::NonNull,
+        }
+    }
+
+    /// nullness of an expression without knowing the values of variables
+    fn of_expression(expr: &AstNode) -> Self {
+        match expr.kind {
+            AstNodeKind::Null => Nullness::Null,
+            AstNodeKind::Number(_)
+            | AstNodeKind::Input
+            | AstNodeKind::Expression(_)
+            | AstNodeKind::Alloc(_)
+            | AstNodeKind::Ref(_)
+            | AstNodeKind::Record(_) => Nullness::NonNull,
+            _ => Nullness::MaybeNull,
+        }
+    }
+}
+
+impl fmt::Display for Nullness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Nullness::Bot => f.write_str("⊥"),
+            Nullness::NonNull => f.write_str("non-null"),
+            Nullness::Null => f.write_str("null"),
+            Nullness::MaybeNull => f.write_str("maybe null"),
+        }
+    }
+}
+
+/// pointer analysis of a program,
+/// with the flow-insensitive nullness of every location: the join of all values stored into it
+/// used when a cell of the heap or of another function is read
+pub struct HeapSummary {
+    andersen: Andersen,
+    // generate from DeclarationAnalysis
+    decl: HashMap<AstNode, AstNode>,
+    cells: HashMap<Location, Nullness>,
+}
+
+impl HeapSummary {
+    fn store(&mut self, loc: Location, value: &AstNode) {
+        let old = self.cell(&loc);
+        self.cells
+            .insert(loc, old.join(&Nullness::of_expression(value)));
+    }
+
+    fn cell(&self, loc: &Location) -> Nullness {
+        self.cells.get(loc).cloned().unwrap_or(Nullness::Bot)
+    }
+}
+
+impl Dfs for HeapSummary {
+    type ResultType = HeapSummary;
+
+    fn new(node: &AstNode) -> Self {
+        Self {
+            andersen: Andersen::new(node),
+            decl: DeclarationAnalysis::work(node),
+            cells: HashMap::new(),
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        match node.kind {
+            AstNodeKind::Function(Function { ref params, .. }) => {
+                // arguments are unknown
+                for param in params {
+                    self.cells
+                        .insert(Location::Var(param.clone()), Nullness::MaybeNull);
+                }
+            }
+            AstNodeKind::Alloc(Alloc { ref expr }) => {
+                self.store(Location::Alloc(node.clone()), expr);
+            }
+            AstNodeKind::Assign(Assign {
+                ref left,
+                ref right,
+            }) => match left.kind {
+                AstNodeKind::Id(_) => {
+                    if let Some(x) = self.decl.get(left).cloned() {
+                        self.store(Location::Var(x), right);
+                    }
+                }
+                AstNodeKind::DerefWrite(DerefWrite { ref expr }) => {
+                    for loc in self.andersen.expression(expr) {
+                        self.store(loc, right);
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        self
+    }
+}
+
+/// collect every `*e` of an expression, with e
+struct DerefCollector {
+    derefs: Vec<(AstNode, AstNode)>,
+}
+
+impl Dfs for DerefCollector {
+    type ResultType = Vec<(AstNode, AstNode)>;
+
+    fn new(_: &AstNode) -> Self {
+        Self { derefs: vec![] }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        if let AstNodeKind::Deref(Deref { ref atom }) = node.kind {
+            self.derefs.push((node.clone(), atom.as_ref().clone()));
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        self.derefs
+    }
+}
+
+/// every dereference of a cfg node: (`*e`, e)
+/// the target of `*e = f` is included, `*e` of `(*e).f = g` is an expression
+fn derefs(node: &CfgNode) -> Vec<(AstNode, AstNode)> {
+    let mut res: Vec<(AstNode, AstNode)> = exprs(node)
+        .into_iter()
+        .flat_map(DerefCollector::work)
+        .collect();
+    if let CfgNode::Stmt(AstNode {
+        kind: AstNodeKind::Assign(Assign { ref left, .. }),
+        ..
+    }) = node
+    {
+        if let AstNodeKind::DerefWrite(DerefWrite { ref expr }) = left.kind {
+            res.push((left.as_ref().clone(), expr.as_ref().clone()))
+        }
+    }
+    res
+}
+
+/// nullness of every param and var (declaration)
+/// None is an unreachable program point
+pub type NullState = Option<HashMap<AstNode, Nullness>>;
+
+/// forward analysis of the nullness of params and vars
+/// without a calling context, params may be null
+pub struct NullAnalysis<'a> {
+    heap: &'a HeapSummary,
+    params: Vec<AstNode>,
+    vars: Vec<AstNode>,
+    /// `&x` is taken, `*p = e` or a call may overwrite x
+    escaped: HashSet<AstNode>,
+}
+
+impl<'a> NullAnalysis<'a> {
+    pub fn new(cfg: &Cfg, heap: &'a HeapSummary) -> Self {
+        if let AstNodeKind::Function(Function {
+            ref params,
+            ref vars,
+            ..
+        }) = cfg.function.kind
+        {
+            Self {
+                heap,
+                params: params.clone(),
+                vars: vars.clone(),
+                escaped: address_taken(&cfg.function, &heap.decl),
+            }
+        } else {
+            unreachable!();
+        }
+    }
+
+    /// nullness of the content of a location
+    fn load(&self, loc: &Location, state: &HashMap<AstNode, Nullness>) -> Nullness {
+        match loc {
+            Location::Var(x) if state.contains_key(x) => state[x],
+            _ => self.heap.cell(loc),
+        }
+    }
+
+    pub fn eval(&self, expr: &AstNode, state: &HashMap<AstNode, Nullness>) -> Nullness {
+        match expr.kind {
+            AstNodeKind::Id(_) => match self.heap.decl.get(expr) {
+                Some(AstNode {
+                    kind: AstNodeKind::Function(_),
+                    ..
+                }) => Nullness::NonNull,
+                Some(x) => self.load(&Location::Var(x.clone()), state),
+                None => Nullness::MaybeNull,
+            },
+            AstNodeKind::Deref(Deref { ref atom }) => self
+                .heap
+                .andersen
+                .expression(atom)
+                .iter()
+                .fold(Nullness::Bot, |acc, loc| acc.join(&self.load(loc, state))),
+            _ => Nullness::of_expression(expr),
+        }
+    }
+
+    /// local variable of an Id
+    fn local(&self, id: &AstNode, state: &HashMap<AstNode, Nullness>) -> Option<AstNode> {
+        self.heap
+            .decl
+            .get(id)
+            .filter(|x| state.contains_key(x))
+            .cloned()
+    }
+}
+
+impl<'a> Dataflow for NullAnalysis<'a> {
+    type Fact = NullState;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    /// vars have no value until they are assigned
+    fn boundary(&self, _: &Cfg) -> Self::Fact {
+        let params = self.params.iter().map(|x| (x.clone(), Nullness::MaybeNull));
+        let vars = self.vars.iter().map(|x| (x.clone(), Nullness::Bot));
+        Some(params.chain(vars).collect())
+    }
+
+    fn init(&self, _: &Cfg) -> Self::Fact {
+        None
+    }
+
+    fn join(&self, a: &Self::Fact, b: &Self::Fact) -> Self::Fact {
+        match (a, b) {
+            (None, x) | (x, None) => x.clone(),
+            (Some(a), Some(b)) => Some(a.iter().map(|(k, v)| (k.clone(), v.join(&b[k]))).collect()),
+        }
+    }
+
+    fn transfer(&self, node: &CfgNode, fact: &Self::Fact) -> Self::Fact {
+        let state = fact.as_ref()?;
+        let mut res = state.clone();
+        if Writes::new(node, &HashSet::new()).writes_heap() {
+            for x in &self.escaped {
+                let v = res[x].join(&self.heap.cell(&Location::Var(x.clone())));
+                res.insert(x.clone(), v);
+            }
+        }
+        // a pointer is not null after being dereferenced,
+        // a null one is reported and the analysis goes on as if it was not
+        for (_, pointer) in derefs(node) {
+            if let Some(x) = self.local(&pointer, state) {
+                res.insert(x, Nullness::NonNull);
+            }
+        }
+        if let CfgNode::Stmt(AstNode {
+            kind:
+                AstNodeKind::Assign(Assign {
+                    ref left,
+                    ref right,
+                }),
+            ..
+        }) = node
+        {
+            let v = self.eval(right, state);
+            match left.kind {
+                AstNodeKind::Id(_) => {
+                    if let Some(x) = self.local(left, state) {
+                        res.insert(x, v);
+                    }
+                }
+                AstNodeKind::DerefWrite(DerefWrite { ref expr }) => {
+                    let targets = self.heap.andersen.expression(expr);
+                    let strong = targets.len() == 1;
+                    for loc in targets {
+                        if let Location::Var(x) = loc {
+                            if res.contains_key(&x) {
+                                let v = if strong { v } else { res[&x].join(&v) };
+                                res.insert(x, v);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(res)
+    }
+
+    /// refine `x == null` and `null == x`
+    fn transfer_edge(&self, from: &CfgNode, branch: Option<bool>, fact: &Self::Fact) -> Self::Fact {
+        let state = fact.as_ref()?;
+        let guard = match (from, branch) {
+            (CfgNode::Guard(stmt), Some(_)) => match stmt.kind {
+                AstNodeKind::If(If { ref guard, .. })
+                | AstNodeKind::While(While { ref guard, .. }) => guard,
+                _ => unreachable!(),
+            },
+            _ => return fact.clone(),
+        };
+        let id = match guard.kind {
+            AstNodeKind::Expression(BinaryOp {
+                op: Op::Equal,
+                ref left,
+                ref right,
+            }) => match (&left.kind, &right.kind) {
+                (AstNodeKind::Id(_), AstNodeKind::Null) => left,
+                (AstNodeKind::Null, AstNodeKind::Id(_)) => right,
+                _ => return fact.clone(),
+            },
+            _ => return fact.clone(),
+        };
+        let mut res = state.clone();
+        if let Some(x) = self.local(id, state) {
+            let v = if branch == Some(true) {
+                res[&x].assume_null()
+            } else {
+                res[&x].assume_non_null()
+            };
+            if v == Nullness::Bot && res[&x] != Nullness::Bot {
+                // infeasible branch
+                return None;
+            }
+            res.insert(x, v);
+        }
+        Some(res)
+    }
+}
+
+/// nullness of every function, the key is the function name
+pub fn null_analysis(program: &AstNode) -> HashMap<String, (Cfg, DataflowResult<NullState>)> {
+    let heap = HeapSummary::work(program);
+    per_function(program, |cfg, _| NullAnalysis::new(cfg, &heap).solve(cfg))
+}
+
+/// report every dereference of a pointer which may be null
+/// Error if it is always null, Warning if it may be null or is not assigned yet
+pub fn null_dereferences(program: &AstNode) -> Vec<(Severity, Diagnostic)> {
+    let heap = HeapSummary::work(program);
+    let mut res = vec![];
+    for cfg in Cfg::from_program(program) {
+        let analysis = NullAnalysis::new(&cfg, &heap);
+        let nullness = analysis.solve(&cfg);
+        for (i, node) in cfg.nodes.iter().enumerate() {
+            let state = match nullness.before[i] {
+                Some(ref state) => state,
+                None => continue,
+            };
+            for (deref, pointer) in derefs(node) {
+                match analysis.eval(&pointer, state) {
+                    Nullness::Null => res.push((
+                        Severity::Error,
+                        Diagnostic::new(
+                            &deref,
+                            format!("null pointer `{}` is dereferenced", pointer),
+                        ),
+                    )),
+                    Nullness::MaybeNull => res.push((
+                        Severity::Warning,
+                        Diagnostic::new(
+                            &deref,
+                            format!("`{}` may be null when dereferenced", pointer),
+                        ),
+                    )),
+                    Nullness::Bot => res.push((
+                        Severity::Warning,
+                        Diagnostic::new(
+                            &deref,
+                            format!("`{}` is dereferenced before it is assigned", pointer),
+                        ),
+                    )),
+                    _ => {}
+                }
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::parse;
+    use crate::diagnostic::Severity;
+    use crate::null_analysis::{null_analysis, null_dereferences, Nullness};
+
+    #[test]
+    fn test_null_dereferences() {
+        let program = parse(
+            "f(q) { var p, x, n; p = alloc null; x = *p; if (input) { p = null; }
+             if (p == null) { p = &x; } x = *p; n = null; *n = 1; x = *q; return x; }
+             g() { var p, c; p = alloc null; c = *p; return *c; }",
+        );
+        // p is non-null after `if (p == null) { p = &x; }`,
+        // and the code after the null dereference `*n = 1` is still analysed
+        let res = null_analysis(&program);
+        let (cfg, nullness) = &res["f"];
+        let i = (0..cfg.len())
+            .find(|i| {
+                cfg.nodes[*i]
+                    .ast()
+                    .is_some_and(|s| s.to_string().contains("*q"))
+            })
+            .unwrap();
+        let p = nullness.before[i]
+            .as_ref()
+            .unwrap()
+            .iter()
+            .find(|(k, _)| k.to_string() == "p")
+            .unwrap()
+            .1;
+        assert_eq!(p, &Nullness::NonNull);
+
+        let res: Vec<(Severity, String)> = null_dereferences(&program)
+            .iter()
+            .map(|(s, d)| (*s, d.to_string()))
+            .collect();
+        assert_eq!(
+            res,
+            vec![
+                (
+                    Severity::Error,
+                    "2:59: null pointer `n` is dereferenced".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "2:71: `q` may be null when dereferenced".to_string()
+                ),
+                // the cell allocated in g only holds null
+                (
+                    Severity::Error,
+                    "3:61: null pointer `c` is dereferenced".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unassigned_dereference() {
+        let program = parse("f(q) { var p, x; x = *p; x = *q; return x; }");
+        let res: Vec<(Severity, String)> = null_dereferences(&program)
+            .iter()
+            .map(|(s, d)| (*s, d.to_string()))
+            .collect();
+        // the code after `*p` is still reachable
+        assert_eq!(
+            res,
+            vec![
+                (
+                    Severity::Warning,
+                    "1:22: `p` is dereferenced before it is assigned".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "1:30: `q` may be null when dereferenced".to_string()
+                ),
+            ]
+        );
+    }
+}
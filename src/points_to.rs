@@ -0,0 +1,108 @@
+use crate::ast_parser::*;
+use crate::dfs::Dfs;
+use std::collections::HashSet;
+use std::fmt;
+
+/// abstract memory location
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+pub enum Location {
+    /// a param or var (declaration)
+    Var(AstNode),
+    /// every cell allocated by an AstNode::Alloc
+    Alloc(AstNode),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Var(x) => f.write_fmt(format_args!("{}", x)),
+            Location::Alloc(a) => f.write_fmt(format_args!("alloc-{}:{}", a.line, a.col)),
+        }
+    }
+}
+
+/// result of a flow-insensitive pointer analysis
+pub trait PointsTo {
+    /// locations the content of `loc` may point to
+    fn targets(&self, loc: &Location) -> HashSet<Location>;
+
+    /// var: a param or var (declaration), see DeclarationAnalysis
+    fn points_to(&self, var: &AstNode) -> HashSet<Location> {
+        self.targets(&Location::Var(var.clone()))
+    }
+
+    /// `*a` and `*b` may be the same cell
+    fn may_alias(&self, a: &AstNode, b: &AstNode) -> bool {
+        !self.points_to(a).is_disjoint(&self.points_to(b))
+    }
+}
+
+/// collect every AstNode::Alloc
+struct AllocCollector {
+    allocs: Vec<AstNode>,
+}
+
+impl Dfs for AllocCollector {
+    type ResultType = Vec<AstNode>;
+
+    fn new(_: &AstNode) -> Self {
+        Self { allocs: vec![] }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        if let AstNodeKind::Alloc(_) = node.kind {
+            self.allocs.push(node.clone());
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        self.allocs
+    }
+}
+
+/// every abstract location of a program, params and vars first
+pub fn locations(program: &AstNode) -> Vec<Location> {
+    let mut res = vec![];
+    if let AstNodeKind::Program(ref functions) = program.kind {
+        for function in functions {
+            if let AstNodeKind::Function(Function {
+                ref params,
+                ref vars,
+                ..
+            }) = function.kind
+            {
+                res.extend(params.iter().chain(vars).cloned().map(Location::Var));
+            }
+        }
+    }
+    res.extend(allocs(program).into_iter().map(Location::Alloc));
+    res
+}
+
+/// every AstNode::Alloc inside a node
+pub fn allocs(node: &AstNode) -> Vec<AstNode> {
+    AllocCollector::work(node)
+}
+
+/// the var with this name of the first function, for tests
+#[cfg(test)]
+pub fn var(program: &AstNode, name: &str) -> AstNode {
+    match program.kind {
+        AstNodeKind::Program(ref functions) => match functions[0].kind {
+            AstNodeKind::Function(Function { ref vars, .. }) => {
+                vars.iter().find(|x| x.to_string() == name).unwrap().clone()
+            }
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// the names of locations, sorted, for tests
+#[cfg(test)]
+pub fn names(locs: HashSet<Location>) -> Vec<String> {
+    let mut res: Vec<String> = locs.iter().map(|l| l.to_string()).collect();
+    res.sort();
+    res
+}
@@ -0,0 +1,193 @@
+use crate::ast_parser::*;
+use crate::available_expressions::Writes;
+use crate::cfg::{address_taken, def, exprs, ids, Cfg, CfgNode};
+use crate::dataflow::{per_function, Dataflow, Direction};
+use std::collections::{HashMap, HashSet};
+
+/// forward may analysis
+/// a fact is the set of reaching (definition, variable)
+/// a definition is an AstNode::Assign or a parameter (AstNode::Id)
+pub struct ReachingDefinitions<'a> {
+    // generate from DeclarationAnalysis
+    decl: &'a HashMap<AstNode, AstNode>,
+    /// `&x` is taken, `*p = e` or a call may define x
+    escaped: HashSet<AstNode>,
+}
+
+impl<'a> ReachingDefinitions<'a> {
+    pub fn new(cfg: &Cfg, decl: &'a HashMap<AstNode, AstNode>) -> Self {
+        Self {
+            decl,
+            escaped: address_taken(&cfg.function, decl),
+        }
+    }
+
+    /// variables defined by a cfg node
+    /// false: weak definition, the old value may survive
+    fn definitions(&self, node: &CfgNode) -> Vec<(AstNode, bool)> {
+        if let Some(x) = def(node, self.decl) {
+            return vec![(x, true)];
+        }
+        let mut res = vec![];
+        if let CfgNode::Stmt(AstNode {
+            kind: AstNodeKind::Assign(Assign { ref left, .. }),
+            ..
+        }) = node
+        {
+            match left.kind {
+                // x.f = e, (x).f = e
+                AstNodeKind::DirectFieldWrite(DirectFieldWrite { id: ref x, .. })
+                | AstNodeKind::IndirectFieldWrite(IndirectFieldWrite { expr: ref x, .. })
+                    if matches!(x.kind, AstNodeKind::Id(_)) =>
+                {
+                    if let Some(x) = self.decl.get(x) {
+                        res.push((x.clone(), false));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if Writes::new(node, &HashSet::new()).writes_heap() {
+            res.extend(self.escaped.iter().map(|x| (x.clone(), false)));
+        }
+        res
+    }
+}
+
+impl<'a> Dataflow for ReachingDefinitions<'a> {
+    type Fact = HashSet<(AstNode, AstNode)>;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn boundary(&self, cfg: &Cfg) -> Self::Fact {
+        if let AstNodeKind::Function(Function { ref params, .. }) = cfg.function.kind {
+            params.iter().map(|x| (x.clone(), x.clone())).collect()
+        } else {
+            unreachable!();
+        }
+    }
+
+    fn init(&self, _: &Cfg) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn join(&self, a: &Self::Fact, b: &Self::Fact) -> Self::Fact {
+        a.union(b).cloned().collect()
+    }
+
+    fn transfer(&self, node: &CfgNode, fact: &Self::Fact) -> Self::Fact {
+        let mut res = fact.clone();
+        for (x, strong) in self.definitions(node) {
+            if strong {
+                res.retain(|(_, y)| y != &x);
+            }
+            res.insert((node.ast().unwrap().clone(), x));
+        }
+        res
+    }
+}
+
+/// use-def and def-use chains of a program
+/// a use is an AstNode::Id reading a param or a var
+/// a definition is an AstNode::Assign or a parameter
+pub struct DefUseChains {
+    use_def: HashMap<AstNode, HashSet<AstNode>>,
+    def_use: HashMap<AstNode, HashSet<AstNode>>,
+}
+
+impl DefUseChains {
+    pub fn new(program: &AstNode) -> Self {
+        let mut res = Self {
+            use_def: HashMap::new(),
+            def_use: HashMap::new(),
+        };
+        let chains = per_function(program, |cfg, decl| {
+            let reaching = ReachingDefinitions::new(cfg, decl).solve(cfg);
+            let mut chains = Self {
+                use_def: HashMap::new(),
+                def_use: HashMap::new(),
+            };
+            for (i, node) in cfg.nodes.iter().enumerate() {
+                for id in exprs(node).into_iter().flat_map(ids) {
+                    let x = match decl.get(&id) {
+                        Some(
+                            x @ AstNode {
+                                kind: AstNodeKind::Id(_),
+                                ..
+                            },
+                        ) => x,
+                        _ => continue,
+                    };
+                    let defs: HashSet<AstNode> = reaching.before[i]
+                        .iter()
+                        .filter(|(_, y)| y == x)
+                        .map(|(d, _)| d.clone())
+                        .collect();
+                    for d in &defs {
+                        chains
+                            .def_use
+                            .entry(d.clone())
+                            .or_default()
+                            .insert(id.clone());
+                    }
+                    chains.use_def.insert(id, defs);
+                }
+            }
+            chains
+        });
+        // the definitions and uses of two functions are apart
+        for (_, (_, chains)) in chains {
+            res.use_def.extend(chains.use_def);
+            res.def_use.extend(chains.def_use);
+        }
+        res
+    }
+
+    /// definitions which may reach a use
+    /// empty if the variable may be read before any assignment
+    pub fn definitions(&self, usage: &AstNode) -> HashSet<AstNode> {
+        self.use_def.get(usage).cloned().unwrap_or_default()
+    }
+
+    /// uses a definition may reach
+    pub fn uses(&self, definition: &AstNode) -> HashSet<AstNode> {
+        self.def_use.get(definition).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::{parse, AstNode, AstNodeKind};
+    use crate::cfg::Cfg;
+    use crate::reaching_definitions::DefUseChains;
+
+    #[test]
+    fn test_def_use_chains() {
+        let source = "f(n) { var x; x = 1; if (n > 0) { x = n; } else { n = 2; } return x + n; }";
+        let program = parse(source);
+        let chains = DefUseChains::new(&program);
+        let cfg = &Cfg::from_program(&program)[0];
+        let stmt = |s: &str| cfg.nodes[cfg.find(source, s)].ast().unwrap().clone();
+        let ret = stmt("return x + n;");
+        let (x, n) = if let AstNodeKind::Expression(ref op) = ret.kind {
+            (op.left.as_ref().clone(), op.right.as_ref().clone())
+        } else {
+            unreachable!();
+        };
+        // x = 1 and x = n
+        assert_eq!(chains.definitions(&x).len(), 2);
+        assert!(chains.definitions(&x).contains(&stmt("x = 1;")));
+        // parameter n and n = 2
+        let param: AstNode = match cfg.function.kind {
+            AstNodeKind::Function(ref f) => f.params[0].clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(chains.definitions(&n).len(), 2);
+        assert!(chains.definitions(&n).contains(&param));
+        // n > 0, x = n, return x + n
+        assert_eq!(chains.uses(&param).len(), 3);
+        assert_eq!(chains.uses(&stmt("x = 1;")).len(), 1);
+    }
+}
@@ -1,10 +1,12 @@
+use crate::ast_parser::Op;
+use crate::value_analysis::ValueLattice;
 use std::cmp::Ordering;
 use std::collections::{HashMap, VecDeque};
-use std::iter::FromIterator;
+use std::fmt;
 use std::lazy::OnceCell;
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
-enum Sign {
+pub enum Sign {
     Top,
     Pos,
     Zero,
@@ -12,7 +14,7 @@ enum Sign {
     Bot,
 }
 
-trait PartialOrd {
+pub trait PartialOrd {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering>;
 }
 
@@ -45,7 +47,6 @@ impl PartialOrd for (Sign, Sign) {
             (Some(Ordering::Less), Some(Ordering::Less)) => Some(Ordering::Less),
             (t, Some(Ordering::Equal)) => t,
             (Some(Ordering::Equal), t) => t,
-            _ => unreachable!(),
         }
     }
 }
@@ -134,8 +135,114 @@ impl Sign {
     }
 }
 
+impl ValueLattice for Sign {
+    fn top() -> Self {
+        Sign::Top
+    }
+
+    fn bot() -> Self {
+        Sign::Bot
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Sign::Bot, x) | (x, Sign::Bot) => *x,
+            (x, y) if x == y => *x,
+            _ => Sign::Top,
+        }
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Sign::Top, x) | (x, Sign::Top) => *x,
+            (x, y) if x == y => *x,
+            _ => Sign::Bot,
+        }
+    }
+
+    fn constant(n: i32) -> Self {
+        match n.cmp(&0) {
+            Ordering::Greater => Sign::Pos,
+            Ordering::Equal => Sign::Zero,
+            Ordering::Less => Sign::Neg,
+        }
+    }
+
+    /// comparisons give 0 or 1, Top if both are possible
+    fn binary(op: &Op, left: &Self, right: &Self) -> Self {
+        use Sign::*;
+        let (left, right) = (*left, *right);
+        if left == Bot || right == Bot {
+            return Bot;
+        }
+        match op {
+            Op::Add => left.plus(right),
+            Op::Subtract => left.minus(right),
+            Op::Multiply => match (left, right) {
+                (Zero, _) | (_, Zero) => Zero,
+                (Top, _) | (_, Top) => Top,
+                (x, y) if x == y => Pos,
+                _ => Neg,
+            },
+            // 1 / 2 == 0
+            Op::Divide => match (left, right) {
+                (_, Zero) => Bot,
+                (Zero, _) => Zero,
+                _ => Top,
+            },
+            Op::Gt => match (left, right) {
+                (Pos, Zero) | (Pos, Neg) | (Zero, Neg) => Pos,
+                (Zero, Zero) | (Neg, Zero) | (Neg, Pos) | (Zero, Pos) => Zero,
+                _ => Top,
+            },
+            Op::Equal => match (left, right) {
+                (Zero, Zero) => Pos,
+                (Top, _) | (_, Top) => Top,
+                (x, y) if x != y => Zero,
+                _ => Top,
+            },
+        }
+    }
+
+    fn greater_than(v: &Self) -> Self {
+        match v {
+            Sign::Pos | Sign::Zero => Sign::Pos,
+            Sign::Bot => Sign::Bot,
+            _ => Sign::Top,
+        }
+    }
+
+    fn less_than(v: &Self) -> Self {
+        match v {
+            Sign::Neg | Sign::Zero => Sign::Neg,
+            Sign::Bot => Sign::Bot,
+            _ => Sign::Top,
+        }
+    }
+
+    /// Zero is the only sign with a single value
+    fn remove(&self, v: &Self) -> Self {
+        match (self, v) {
+            (Sign::Zero, Sign::Zero) => Sign::Bot,
+            _ => *self,
+        }
+    }
+}
+
+impl fmt::Display for Sign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Sign::Top => "⊤",
+            Sign::Pos => "+",
+            Sign::Zero => "0",
+            Sign::Neg => "-",
+            Sign::Bot => "⊥",
+        })
+    }
+}
+
 // O(n^3)
-fn check_monotone(f: &dyn Fn(Sign, Sign) -> Sign) -> bool {
+pub fn check_monotone(f: &dyn Fn(Sign, Sign) -> Sign) -> bool {
     use Sign::*;
     let mut vq = VecDeque::new();
     vq.push_front((Bot, Bot));
@@ -181,15 +288,12 @@ fn check_monotone(f: &dyn Fn(Sign, Sign) -> Sign) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use crate::ast_parser::parse;
-    use crate::declaration_analysis::DeclarationAnalysis;
-    use crate::dfs::Dfs;
+    use crate::ast_parser::{parse, Op};
     use crate::sign_lattice::check_monotone;
     use crate::sign_lattice::PartialOrd;
     use crate::sign_lattice::Sign;
+    use crate::value_analysis::{report, value_analysis, ValueLattice};
     use std::cmp::Ordering;
-    use std::collections::HashMap;
-    use std::fs;
 
     #[test]
     fn test_sign_ord() {
@@ -215,5 +319,24 @@ mod tests {
     fn test_sign_lattice() {
         assert!(check_monotone(&Sign::plus));
         assert!(check_monotone(&Sign::minus));
+        assert!(check_monotone(&|a, b| Sign::binary(&Op::Multiply, &a, &b)));
+        assert!(check_monotone(&|a, b| Sign::binary(&Op::Gt, &a, &b)));
+    }
+
+    #[test]
+    fn test_sign_refinement() {
+        let program = parse(
+            "main() { var x, y; x = input; y = 0;
+             if (x > 0) { y = x; } else { if (x == 0) { y = x; } }
+             return y; }",
+        );
+        let res = value_analysis::<Sign>(&program);
+        let (cfg, values) = &res["main"];
+        let lines = report(cfg, values);
+        // then branch: x > 0
+        assert_eq!(lines[3], "2:27: x = +, y = 0");
+        // x == 0
+        assert_eq!(lines[5], "2:57: x = 0, y = 0");
+        assert_eq!(lines[6], "3:21: x = ⊤, y = ⊤");
     }
 }
@@ -0,0 +1,242 @@
+use crate::ast_parser::*;
+use crate::control_flow_analysis::ControlFlowAnalysis;
+use crate::declaration_analysis::DeclarationAnalysis;
+use crate::dfs::Dfs;
+use crate::points_to::{locations, Location, PointsTo};
+use crate::term::*;
+use crate::union_find::UnionFindSolver;
+use std::collections::{HashMap, HashSet};
+
+/// generate the unification constraints of a program, see the TIP book
+/// ⟦x⟧ = ⭡⟦y⟧ means x points to the cell y
+struct SteensgaardConstraints {
+    union_find: UnionFindSolver,
+    // generate from DeclarationAnalysis
+    decl: HashMap<AstNode, AstNode>,
+    locations: Vec<Location>,
+    /// resolve the calls through function values
+    cfa: ControlFlowAnalysis,
+    /// AstNode::Function => term of the returned value
+    rets: HashMap<AstNode, Term>,
+}
+
+impl SteensgaardConstraints {
+    fn term(&self, node: &AstNode) -> Term {
+        match self.decl.get(node) {
+            Some(res) if !matches!(res.kind, AstNodeKind::Function(_)) => {
+                Term::Var(Var::VarType(res.clone()))
+            }
+            _ => Term::Var(Var::VarType(node.clone())),
+        }
+    }
+
+    fn pointer(of: Term) -> Term {
+        Term::Cons(Cons::PointerType(PointerType { of: Box::new(of) }))
+    }
+}
+
+fn location_term(loc: &Location) -> Term {
+    match loc {
+        Location::Var(x) | Location::Alloc(x) => Term::Var(Var::VarType(x.clone())),
+    }
+}
+
+impl Dfs for SteensgaardConstraints {
+    type ResultType = Steensgaard;
+
+    fn new(node: &AstNode) -> Self {
+        let functions = match node.kind {
+            AstNodeKind::Program(ref functions) => functions,
+            _ => unreachable!(),
+        };
+        let locations = locations(node);
+        let mut supply = VarSupply::new();
+        let mut union_find = UnionFindSolver::new();
+        // every location has a class, even if it is never used
+        for loc in &locations {
+            let t = location_term(loc);
+            union_find.union(&t, &t);
+        }
+        Self {
+            union_find,
+            decl: DeclarationAnalysis::work(node),
+            locations,
+            rets: functions
+                .iter()
+                .map(|f| (f.clone(), supply.fresh()))
+                .collect(),
+            cfa: ControlFlowAnalysis::new(node),
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        match node.kind {
+            // &x: ⟦&x⟧ = ⭡⟦x⟧
+            AstNodeKind::Ref(Ref { ref id }) => {
+                self.union_find
+                    .union(&self.term(node), &Self::pointer(self.term(id)));
+            }
+            // alloc e: ⟦alloc e⟧ = ⭡⟦alloc-i⟧, ⟦alloc-i⟧ = ⟦e⟧
+            AstNodeKind::Alloc(Alloc { ref expr }) => {
+                let cell = location_term(&Location::Alloc(node.clone()));
+                self.union_find
+                    .union(&self.term(node), &Self::pointer(cell.clone()));
+                self.union_find.union(&cell, &self.term(expr));
+            }
+            // *e: ⟦e⟧ = ⭡⟦*e⟧
+            AstNodeKind::Deref(Deref { ref atom }) => {
+                self.union_find
+                    .union(&self.term(atom), &Self::pointer(self.term(node)));
+            }
+            AstNodeKind::Assign(Assign {
+                ref left,
+                ref right,
+            }) => match left.kind {
+                AstNodeKind::Id(_) => {
+                    self.union_find.union(&self.term(left), &self.term(right));
+                }
+                AstNodeKind::DirectFieldWrite(DirectFieldWrite { ref id, .. }) => {
+                    self.union_find.union(&self.term(id), &self.term(right));
+                }
+                // *e = f: ⟦e⟧ = ⭡⟦f⟧
+                AstNodeKind::DerefWrite(DerefWrite { ref expr }) => {
+                    self.union_find
+                        .union(&self.term(expr), &Self::pointer(self.term(right)));
+                }
+                // (*e).f = g: ⟦e⟧ = ⭡⟦g⟧
+                AstNodeKind::IndirectFieldWrite(IndirectFieldWrite { ref expr, .. }) => {
+                    let (to, from) = match expr.kind {
+                        AstNodeKind::Deref(Deref { ref atom }) => {
+                            (self.term(atom), Self::pointer(self.term(right)))
+                        }
+                        _ => (self.term(expr), self.term(right)),
+                    };
+                    self.union_find.union(&to, &from);
+                }
+                _ => unreachable!(),
+            },
+            // field-insensitive: a record is unified with all its fields
+            AstNodeKind::Record(ref fields) => {
+                for field in fields {
+                    self.union_find
+                        .union(&self.term(node), &self.term(&field.expression));
+                }
+            }
+            AstNodeKind::FieldAccess(FieldAccess { ref name, .. }) => {
+                self.union_find.union(&self.term(node), &self.term(name));
+            }
+            AstNodeKind::FunApp(ref call) => {
+                for f in self.cfa.callees(node) {
+                    if let AstNodeKind::Function(Function { ref params, .. }) = f.kind {
+                        for (arg, param) in call.params.iter().zip(params) {
+                            self.union_find.union(&self.term(arg), &self.term(param));
+                        }
+                    }
+                    self.union_find.union(&self.term(node), &self.rets[&f]);
+                }
+            }
+            AstNodeKind::Function(Function { ref ret, .. }) => {
+                self.union_find.union(&self.rets[node], &self.term(ret));
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        Steensgaard {
+            solution: self.union_find.solution(),
+            locations: self.locations,
+        }
+    }
+}
+
+/// unification-based pointer analysis
+/// almost linear, but less precise than Andersen:
+/// all the targets of a pointer are merged into one class
+pub struct Steensgaard {
+    solution: HashMap<Term, Term>,
+    locations: Vec<Location>,
+}
+
+impl Steensgaard {
+    pub fn new(program: &AstNode) -> Self {
+        SteensgaardConstraints::work(program)
+    }
+
+    fn find(&self, t: &Term) -> Term {
+        self.solution.get(t).cloned().unwrap_or_else(|| t.clone())
+    }
+
+    /// representative term of a location, ⭡α if it holds a pointer
+    pub fn cell(&self, loc: &Location) -> Term {
+        self.find(&location_term(loc))
+    }
+
+    /// locations which are unified together
+    pub fn classes(&self) -> Vec<HashSet<Location>> {
+        let mut classes: HashMap<Term, HashSet<Location>> = HashMap::new();
+        for loc in &self.locations {
+            classes
+                .entry(self.cell(loc))
+                .or_default()
+                .insert(loc.clone());
+        }
+        classes.into_values().collect()
+    }
+}
+
+impl PointsTo for Steensgaard {
+    fn targets(&self, loc: &Location) -> HashSet<Location> {
+        match self.cell(loc) {
+            Term::Cons(Cons::PointerType(PointerType { of })) => {
+                let of = self.find(&of);
+                self.locations
+                    .iter()
+                    .filter(|l| self.cell(l) == of)
+                    .cloned()
+                    .collect()
+            }
+            _ => HashSet::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::andersen::Andersen;
+    use crate::ast_parser::*;
+    use crate::points_to::{names, var, Location, PointsTo};
+    use crate::steensgaard::Steensgaard;
+
+    #[test]
+    fn test_steensgaard() {
+        let program = parse(
+            "main() { var a, b, c, p, q; p = &a; q = &b; if (input) { p = q; } c = &p; return 0; }",
+        );
+        let steensgaard = Steensgaard::new(&program);
+        // p = q merges the targets of p and q
+        assert_eq!(
+            names(steensgaard.points_to(&var(&program, "q"))),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            names(steensgaard.points_to(&var(&program, "c"))),
+            vec!["p", "q"]
+        );
+        assert!(steensgaard.may_alias(&var(&program, "p"), &var(&program, "q")));
+        assert_eq!(
+            format!("{:?}", steensgaard.cell(&Location::Var(var(&program, "c"))))
+                .chars()
+                .next(),
+            Some('⭡')
+        );
+        let mut classes: Vec<Vec<String>> = steensgaard.classes().into_iter().map(names).collect();
+        classes.sort();
+        assert_eq!(classes, vec![vec!["a", "b"], vec!["c"], vec!["p", "q"]]);
+
+        // Andersen keeps the targets of q apart
+        let andersen = Andersen::new(&program);
+        assert_eq!(names(andersen.points_to(&var(&program, "q"))), vec!["b"]);
+    }
+}
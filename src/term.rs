@@ -47,11 +47,83 @@ impl From<RecordType> for Term {
 }
 
 impl Term {
-    pub fn fresh_var() -> Self {
-        static mut INDEX: usize = 0;
-        unsafe {
-            INDEX += 1;
-            Term::Var(Var::FreshVarType(INDEX))
+    /// variables not bound by a μ, in order of appearance
+    pub fn free_vars(&self) -> Vec<Term> {
+        let mut res = vec![];
+        self.collect_free_vars(&mut vec![], &mut res);
+        res
+    }
+
+    fn collect_free_vars(&self, bound: &mut Vec<Term>, res: &mut Vec<Term>) {
+        match self {
+            Term::Var(_) => {
+                if !bound.contains(self) && !res.contains(self) {
+                    res.push(self.clone());
+                }
+            }
+            Term::Cons(Cons::FunctionType(FunctionType { params, ret })) => {
+                for p in params {
+                    p.collect_free_vars(bound, res);
+                }
+                ret.collect_free_vars(bound, res);
+            }
+            Term::Cons(Cons::PointerType(PointerType { of })) => of.collect_free_vars(bound, res),
+            Term::Cons(Cons::RecordType(RecordType { fields, rest })) => {
+                let mut names: Vec<&String> = fields.keys().collect();
+                names.sort();
+                for name in names {
+                    fields[name].collect_free_vars(bound, res);
+                }
+                if let Some(rest) = rest {
+                    rest.collect_free_vars(bound, res);
+                }
+            }
+            Term::Cons(_) => {}
+            Term::Mu(Mu::RecursiveType(RecursiveType { v, t })) => {
+                bound.push(v.as_ref().clone());
+                t.collect_free_vars(bound, res);
+                bound.pop();
+            }
+        }
+    }
+
+    /// a copy with a fresh variable for every free variable
+    /// used for a polymorphic type at each use
+    pub fn instantiate(&self, supply: &mut VarSupply) -> Term {
+        self.free_vars()
+            .iter()
+            .fold(self.clone(), |t, v| t.substitute(v, &supply.fresh()))
+    }
+
+    /// replace every variable, μ-bound ones included, by f(variable)
+    /// unlike a chain of substitute, a variable is never replaced twice
+    pub fn map_vars(&self, f: &mut impl FnMut(&Term) -> Term) -> Term {
+        match self {
+            Term::Var(_) => f(self),
+            Term::Cons(Cons::FunctionType(FunctionType { params, ret })) => {
+                Term::from(FunctionType {
+                    params: params.iter().map(|p| p.map_vars(f)).collect(),
+                    ret: Box::new(ret.map_vars(f)),
+                })
+            }
+            Term::Cons(Cons::PointerType(PointerType { of })) => Term::from(PointerType {
+                of: Box::new(of.map_vars(f)),
+            }),
+            Term::Cons(Cons::RecordType(RecordType { fields, rest })) => {
+                let mut r = RecordType::new();
+                for (k, v) in fields {
+                    r.fields.insert(k.clone(), v.map_vars(f));
+                }
+                r.rest = rest.as_ref().map(|rest| Box::new(rest.map_vars(f)));
+                Term::from(r)
+            }
+            Term::Cons(_) => self.clone(),
+            Term::Mu(Mu::RecursiveType(RecursiveType { v, t })) => {
+                Term::Mu(Mu::RecursiveType(RecursiveType {
+                    v: Box::new(v.map_vars(f)),
+                    t: Box::new(t.map_vars(f)),
+                }))
+            }
         }
     }
 
@@ -80,11 +152,14 @@ impl Term {
                         of: Box::new(of.substitute(from, to)),
                     }))
                 }
-                Cons::RecordType(RecordType { fields, .. }) => {
+                Cons::RecordType(RecordType { fields, rest }) => {
                     let mut r = RecordType::new();
                     for (k, v) in fields {
                         r.fields.insert(k.to_string(), v.substitute(from, to));
                     }
+                    r.rest = rest
+                        .as_ref()
+                        .map(|rest| Box::new(rest.substitute(from, to)));
                     Term::Cons(Cons::RecordType(r))
                 }
                 Cons::AbsentFieldType => self.clone(),
@@ -103,6 +178,24 @@ impl Term {
     }
 }
 
+/// fresh type variables x1, x2, .. of one analysis
+/// each analysis owns its supply, so names don't depend on what ran before
+#[derive(Debug, Default)]
+pub struct VarSupply {
+    next: usize,
+}
+
+impl VarSupply {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn fresh(&mut self) -> Term {
+        self.next += 1;
+        Term::Var(Var::FreshVarType(self.next))
+    }
+}
+
 #[derive(Hash, Eq, PartialEq, Clone)]
 pub enum Var {
     FreshVarType(usize),
@@ -129,7 +222,7 @@ pub enum Cons {
     FunctionType(FunctionType),
     PointerType(PointerType),
     RecordType(RecordType),
-    // used in RecordType. If a field can't infer type
+    // a field known to be absent, like a field missing from a closed RecordType
     AbsentFieldType,
 }
 
@@ -174,8 +267,11 @@ impl Cons {
                 }
                 false
             }
-            Cons::RecordType(RecordType { ref fields, .. }) => {
-                for f in fields.values() {
+            Cons::RecordType(RecordType {
+                ref fields,
+                ref rest,
+            }) => {
+                for f in fields.values().chain(rest.as_deref()) {
                     if f == t {
                         return true;
                     }
@@ -256,44 +352,55 @@ impl fmt::Debug for PointerType {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct RecordType {
-    /// initial with HashMap: x=>Term::FreshVarType
+    /// only the fields known to exist
     pub fields: HashMap<String, Term>,
-    /// HashMap can't be Hash
-    /// so we use index to distinguish two RecordType
-    index: usize,
+    /// the row variable standing for the other fields, bound to a record of them by unification
+    /// None: a closed record, every other field is absent
+    pub rest: Option<Box<Term>>,
 }
-impl PartialEq for RecordType {
-    fn eq(&self, other: &RecordType) -> bool {
-        self.fields == other.fields
+
+impl Default for RecordType {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl Eq for RecordType {}
-
 impl RecordType {
+    /// closed and empty
     pub fn new() -> Self {
-        static mut INDEX: usize = 0;
-        unsafe {
-            INDEX += 1;
-            RecordType {
-                index: INDEX,
-                fields: HashMap::new(),
-            }
+        RecordType {
+            fields: HashMap::new(),
+            rest: None,
         }
     }
+
+    /// some field with this type, and maybe others
+    pub fn open(field: &str, t: Term, rest: Term) -> Self {
+        let mut res = RecordType::new();
+        res.fields.insert(field.to_string(), t);
+        res.rest = Some(Box::new(rest));
+        res
+    }
 }
 
+/// HashMap can't be Hash, so the fields are hashed in name order
 impl Hash for RecordType {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.index.hash(state);
+        let mut fields: Vec<(&String, &Term)> = self.fields.iter().collect();
+        fields.sort_by(|a, b| a.0.cmp(b.0));
+        fields.hash(state);
+        self.rest.hash(state);
     }
 }
 
 impl fmt::Debug for RecordType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!("{:?}", self.fields))?;
+        if let Some(rest) = &self.rest {
+            f.write_fmt(format_args!("|{:?}", rest))?;
+        }
         Ok(())
     }
 }
@@ -303,3 +410,11 @@ pub struct RecursiveType {
     pub v: Box<Term>,
     pub t: Box<Term>,
 }
+
+impl RecursiveType {
+    /// μα.τ => τ[α := μα.τ]
+    pub fn unfold(&self) -> Term {
+        self.t
+            .substitute(&self.v, &Term::Mu(Mu::RecursiveType(self.clone())))
+    }
+}
@@ -1,58 +1,298 @@
 use crate::ast_parser::*;
+use crate::callgraph::CallGraph;
 use crate::declaration_analysis::DeclarationAnalysis;
 use crate::dfs::Dfs;
-use crate::field_collector::FieldCollector;
-use crate::term::Var::VarType;
 use crate::term::*;
-use crate::union_find::UnionFindSolver;
+use crate::union_find::{Clash, UnionFindSolver};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// a constraint which can't be solved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    /// the node whose constraint fails
+    pub node: AstNode,
+    /// the constructors which clash
+    pub left: Term,
+    pub right: Term,
+    /// the nodes whose constraints introduced `left` and `right`
+    pub left_origin: AstNode,
+    pub right_origin: AstNode,
+    /// why `node` has a constraint
+    pub explanation: String,
+}
+
+impl TypeError {
+    fn new(node: &AstNode, clash: Clash) -> Self {
+        let explanation = match clash.field {
+            Some(ref field) => format!("the field `{}` is missing", field),
+            None => explanation(node).to_string(),
+        };
+        Self {
+            node: node.clone(),
+            left_origin: clash.left_origin.unwrap_or_else(|| node.clone()),
+            right_origin: clash.right_origin.unwrap_or_else(|| node.clone()),
+            left: clash.left,
+            right: clash.right,
+            explanation,
+        }
+    }
+}
+
+/// a short name of the outermost constructor
+fn describe(t: &Term) -> String {
+    match t {
+        Term::Var(_) => "a type variable".to_string(),
+        Term::Cons(Cons::IntType) => "int".to_string(),
+        Term::Cons(Cons::PointerType(_)) => "a pointer".to_string(),
+        Term::Cons(Cons::FunctionType(FunctionType { params, .. })) if params.len() == 1 => {
+            "a function of 1 parameter".to_string()
+        }
+        Term::Cons(Cons::FunctionType(FunctionType { params, .. })) => {
+            format!("a function of {} parameters", params.len())
+        }
+        // the fields known so far, `..` if there may be others
+        Term::Cons(Cons::RecordType(RecordType { fields, rest })) => {
+            let mut names: Vec<&str> = fields
+                .iter()
+                .filter(|(_, t)| !matches!(t, Term::Cons(Cons::AbsentFieldType)))
+                .map(|(k, _)| k.as_str())
+                .collect();
+            names.sort_unstable();
+            if rest.is_some() {
+                names.push("..");
+            }
+            format!("a record {{{}}}", names.join(", "))
+        }
+        Term::Cons(Cons::AbsentFieldType) => "an absent field".to_string(),
+        Term::Mu(_) => "a recursive type".to_string(),
+    }
+}
+
+/// why a node has a type constraint
+fn explanation(node: &AstNode) -> &'static str {
+    match node.kind {
+        AstNodeKind::Output(_) => "only integers can be printed",
+        AstNodeKind::Assign(Assign { ref left, .. }) => match left.kind {
+            AstNodeKind::Id(_) => "both sides of an assignment have the same type",
+            AstNodeKind::DerefWrite(_) => "`*e = f` writes through a pointer",
+            _ => "only a record has fields",
+        },
+        AstNodeKind::If(_) | AstNodeKind::While(_) => "a condition is an integer",
+        AstNodeKind::Function(_) => {
+            "a function has the type of its parameters and returned value, main returns an integer"
+        }
+        AstNodeKind::Deref(_) => "only a pointer can be dereferenced",
+        AstNodeKind::FunApp(_) => {
+            "the arguments and the result of a call match the called function"
+        }
+        AstNodeKind::FieldAccess(_) => "only a record has fields",
+        AstNodeKind::Expression(BinaryOp { op: Op::Equal, .. }) => {
+            "both sides of `==` have the same type"
+        }
+        AstNodeKind::Expression(_) => "arithmetic and comparisons are on integers",
+        _ => "the value of this expression does not match its uses",
+    }
+}
+
+/// `{line}:{col}: cannot unify {left} with {right}: {explanation} ({left} from .., {right} from ..)`
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (left, right) = (describe(&self.left), describe(&self.right));
+        f.write_fmt(format_args!(
+            "{}:{}: cannot unify {} with {}: {} ({} from {}:{}, {} from {}:{})",
+            self.node.line,
+            self.node.col,
+            left,
+            right,
+            self.explanation,
+            left,
+            self.left_origin.line,
+            self.left_origin.col,
+            right,
+            self.right_origin.line,
+            self.right_origin.col
+        ))
+    }
+}
 
 struct TypeAnalysis {
     union_find: UnionFindSolver,
     // generate from DeclarationAnalysis
     decl: HashMap<AstNode, AstNode>,
-    all_fields: Vec<String>,
+    errors: Vec<TypeError>,
+    /// every expression, param, var and function, their types are closed in `finish`
+    nodes: Vec<AstNode>,
+    functions: HashMap<String, AstNode>,
+    /// AstNode::Function => closed type, its free variables stand for any type
+    /// only used by TypeInference::polymorphic
+    schemes: HashMap<AstNode, Term>,
+    supply: VarSupply,
 }
 
 impl TypeAnalysis {
-    fn astNode2Term(&self, node: &AstNode) -> Term {
+    fn term_of_node(&self, node: &AstNode) -> Term {
         match self.decl.get(node) {
+            // each use of a polymorphic function has its own instance
+            Some(res) if self.schemes.contains_key(res) => Term::Var(Var::VarType(node.clone())),
             Some(res) => Term::Var(Var::VarType(res.clone())),
             None => Term::Var(Var::VarType(node.clone())),
         }
     }
 
-    fn new_record(&self) -> RecordType {
-        let mut rec = RecordType::new();
-        for field in &self.all_fields {
-            rec.fields.insert(field.clone(), Term::fresh_var());
+    /// t1 = t2, the constraint of `node`
+    fn unify(&mut self, t1: &Term, t2: &Term, node: &AstNode) {
+        for clash in self.union_find.union_at(t1, t2, node, &mut self.supply) {
+            self.errors.push(TypeError::new(node, clash));
         }
-        rec
     }
-}
 
-impl Dfs for TypeAnalysis {
-    type ResultType = HashMap<Term, Term>;
+    /// remember the nodes which have a type
+    fn record(&mut self, node: &AstNode) {
+        match node.kind {
+            AstNodeKind::Function(Function {
+                ref name,
+                ref params,
+                ref vars,
+                ..
+            }) => {
+                self.functions.insert(name.clone(), node.clone());
+                self.nodes.push(node.clone());
+                self.nodes.extend(params.iter().chain(vars).cloned());
+            }
+            AstNodeKind::Id(_)
+            | AstNodeKind::Number(_)
+            | AstNodeKind::Input
+            | AstNodeKind::Record(_)
+            | AstNodeKind::Null
+            | AstNodeKind::Alloc(_)
+            | AstNodeKind::Ref(_)
+            | AstNodeKind::Deref(_)
+            | AstNodeKind::FunApp(_)
+            | AstNodeKind::FieldAccess(_)
+            | AstNodeKind::Expression(_) => self.nodes.push(node.clone()),
+            _ => {}
+        }
+    }
 
-    fn new(node: &AstNode) -> Self {
-        let all_fields = FieldCollector::work(node);
-        let decl = DeclarationAnalysis::work(node);
+    /// decl: DeclarationAnalysis of the whole program
+    fn with_decl(decl: HashMap<AstNode, AstNode>) -> Self {
         Self {
             union_find: UnionFindSolver::new(),
-            all_fields,
             decl,
+            errors: vec![],
+            nodes: vec![],
+            functions: HashMap::new(),
+            schemes: HashMap::new(),
+            supply: VarSupply::new(),
+        }
+    }
+
+    /// the closed types, and decl given back for the next analysis
+    fn solve(mut self) -> (TypeInference, HashMap<AstNode, AstNode>) {
+        let functions: Vec<AstNode> = self
+            .nodes
+            .iter()
+            .filter(|n| matches!(n.kind, AstNodeKind::Function(_)))
+            .cloned()
+            .collect();
+        for function in &functions {
+            self.declare(function);
         }
+        let terms: Vec<Term> = self.nodes.iter().map(|n| self.term_of_node(n)).collect();
+        let env = self.union_find.solution();
+        let mut types = HashMap::new();
+        let mut fresh_vars = HashMap::<Term, Term>::new();
+        let mut supply = self.supply;
+        for (node, t) in self.nodes.into_iter().zip(terms) {
+            let root = env.get(&t).unwrap_or(&t);
+            let closed = close(root, &env, &mut fresh_vars, &mut supply);
+            types.insert(node, closed);
+        }
+        let res = TypeInference {
+            env: TypeEnv { types },
+            errors: self.errors,
+            functions: self.functions,
+            supply,
+        };
+        (res, self.decl)
+    }
+
+    /// unify the annotations of a function with the inferred types
+    /// the type variables of the annotations are renamed apart from those of the analysis
+    fn declare(&mut self, function: &AstNode) {
+        let (annotations, ret_type, ret) = match function.kind {
+            AstNodeKind::Function(Function {
+                ref annotations,
+                ref ret_type,
+                ref ret,
+                ..
+            }) => (annotations, ret_type, ret),
+            _ => unreachable!(),
+        };
+        let mut renaming = HashMap::new();
+        let declared: Vec<(&AstNode, Term)> = annotations
+            .iter()
+            .map(|(x, t)| (x, t))
+            .chain(ret_type.iter().map(|t| (function, t.as_ref())))
+            .map(|(node, t)| {
+                let supply = &mut self.supply;
+                let t = t.map_vars(&mut |v| {
+                    renaming
+                        .entry(v.clone())
+                        .or_insert_with(|| supply.fresh())
+                        .clone()
+                });
+                (node, t)
+            })
+            .collect();
+        for (node, t) in declared {
+            let inferred = if node == function {
+                self.term_of_node(ret)
+            } else {
+                self.term_of_node(node)
+            };
+            for clash in self
+                .union_find
+                .union_at(&inferred, &t, node, &mut self.supply)
+            {
+                self.errors.push(TypeError {
+                    explanation: "the declared type does not match the inferred type".to_string(),
+                    ..TypeError::new(node, clash)
+                });
+            }
+        }
+    }
+
+    /// a record with the field `field` of type t, and maybe others
+    fn open_record(&mut self, field: &str, t: Term) -> Term {
+        let rest = self.supply.fresh();
+        Term::from(RecordType::open(field, t, rest))
+    }
+}
+
+impl Dfs for TypeAnalysis {
+    type ResultType = TypeInference;
+
+    fn new(node: &AstNode) -> Self {
+        Self::with_decl(DeclarationAnalysis::work(node))
     }
 
     fn visit(&mut self, node: &AstNode) -> bool {
+        self.record(node);
         match &node.kind {
-            AstNodeKind::Id(_) => {}
+            AstNodeKind::Id(_) => {
+                let schemes = &self.schemes;
+                if let Some(scheme) = self.decl.get(node).and_then(|f| schemes.get(f)) {
+                    let t = scheme.instantiate(&mut self.supply);
+                    self.unify(&self.term_of_node(node), &t, node);
+                }
+            }
             AstNodeKind::DirectFieldWrite(_) => {}
             AstNodeKind::IndirectFieldWrite(_) => {}
             AstNodeKind::DerefWrite(_) => {}
             AstNodeKind::Output(Output { expr }) => {
-                self.union_find
-                    .union(&self.astNode2Term(expr), &Term::Cons(Cons::IntType));
+                self.unify(&self.term_of_node(expr), &Term::Cons(Cons::IntType), node);
             }
             AstNodeKind::Error(_) => {}
             AstNodeKind::Assign(Assign {
@@ -62,35 +302,28 @@ impl Dfs for TypeAnalysis {
             }) => {
                 match &left.kind {
                     AstNodeKind::Id(_) => {
-                        self.union_find
-                            .union(&self.astNode2Term(left), &self.astNode2Term(right));
+                        self.unify(&self.term_of_node(left), &self.term_of_node(right), node);
                     }
                     AstNodeKind::DirectFieldWrite(DirectFieldWrite { field, id }) => {
-                        let mut rec = self.new_record();
-                        rec.fields.insert(field.clone(), self.astNode2Term(right));
-                        self.union_find
-                            .union(&self.astNode2Term(id), &Term::Cons(Cons::RecordType(rec)));
+                        let rec = self.open_record(field, self.term_of_node(right));
+                        self.unify(&self.term_of_node(id), &rec, node);
                     }
                     AstNodeKind::IndirectFieldWrite(IndirectFieldWrite {
                         ref expr,
                         ref field,
                     }) => {
-                        let mut rec = self.new_record();
-                        rec.fields.insert(field.clone(), self.astNode2Term(right));
-                        self.union_find.union(
-                            &self.astNode2Term(expr),
-                            &Term::Cons(Cons::PointerType(PointerType {
-                                of: Box::new(Term::Cons(Cons::RecordType(rec))),
-                            })),
-                        );
+                        // (e).f = g, e is a record, usually *p
+                        let rec = self.open_record(field, self.term_of_node(right));
+                        self.unify(&self.term_of_node(expr), &rec, node);
                     }
                     // *c=f
                     AstNodeKind::DerefWrite(DerefWrite { ref expr }) => {
-                        self.union_find.union(
-                            &self.astNode2Term(expr),
+                        self.unify(
+                            &self.term_of_node(expr),
                             &Term::Cons(Cons::PointerType(PointerType {
-                                of: Box::new(self.astNode2Term(right)),
+                                of: Box::new(self.term_of_node(right)),
                             })),
+                            node,
                         );
                     }
                     _ => {
@@ -99,12 +332,10 @@ impl Dfs for TypeAnalysis {
                 }
             }
             AstNodeKind::If(If { ref guard, .. }) => {
-                self.union_find
-                    .union(&self.astNode2Term(guard), &Term::Cons(Cons::IntType));
+                self.unify(&self.term_of_node(guard), &Term::Cons(Cons::IntType), node);
             }
             AstNodeKind::While(While { ref guard, .. }) => {
-                self.union_find
-                    .union(&self.astNode2Term(guard), &Term::Cons(Cons::IntType));
+                self.unify(&self.term_of_node(guard), &Term::Cons(Cons::IntType), node);
             }
             AstNodeKind::Block(_) => {}
             AstNodeKind::Function(Function {
@@ -115,68 +346,74 @@ impl Dfs for TypeAnalysis {
             }) => {
                 let ft = if name == "main" {
                     FunctionType {
-                        params: params.iter().map(|x| self.astNode2Term(x)).collect(),
+                        params: params.iter().map(|x| self.term_of_node(x)).collect(),
                         ret: Box::new(Term::Cons(Cons::IntType)),
                     }
                 } else {
                     FunctionType {
-                        params: params.iter().map(|x| self.astNode2Term(x)).collect(),
-                        ret: Box::new(self.astNode2Term(ret)),
+                        params: params.iter().map(|x| self.term_of_node(x)).collect(),
+                        ret: Box::new(self.term_of_node(ret)),
                     }
                 };
-                self.union_find.union(
-                    &self.astNode2Term(node),
+                self.unify(
+                    &self.term_of_node(node),
                     &Term::Cons(Cons::FunctionType(ft)),
+                    node,
                 );
             }
             AstNodeKind::Program(_) => {}
             AstNodeKind::Number(_) => {
-                self.union_find
-                    .union(&self.astNode2Term(node), &Term::Cons(Cons::IntType));
+                self.unify(&self.term_of_node(node), &Term::Cons(Cons::IntType), node);
             }
             AstNodeKind::Input => {
-                self.union_find
-                    .union(&self.astNode2Term(node), &Term::Cons(Cons::IntType));
+                self.unify(&self.term_of_node(node), &Term::Cons(Cons::IntType), node);
             }
+            // a record has exactly the fields it is built with
             AstNodeKind::Record(ref fields) => {
-                let mut rec = self.new_record();
+                let mut rec = RecordType::new();
                 for field in fields {
                     rec.fields
-                        .insert(field.name.clone(), self.astNode2Term(&field.expression));
+                        .insert(field.name.clone(), self.term_of_node(&field.expression));
                 }
-                self.union_find
-                    .union(&self.astNode2Term(node), &Term::Cons(Cons::RecordType(rec)));
+                self.unify(
+                    &self.term_of_node(node),
+                    &Term::Cons(Cons::RecordType(rec)),
+                    node,
+                );
             }
             AstNodeKind::Null => {
-                self.union_find.union(
-                    &self.astNode2Term(node),
-                    &Term::Cons(Cons::PointerType(PointerType {
-                        of: Box::new(Term::fresh_var()),
-                    })),
+                let of = Box::new(self.supply.fresh());
+                self.unify(
+                    &self.term_of_node(node),
+                    &Term::Cons(Cons::PointerType(PointerType { of })),
+                    node,
                 );
             }
             AstNodeKind::Alloc(Alloc { ref expr }) => {
-                self.union_find.union(
-                    &self.astNode2Term(node),
+                self.unify(
+                    &self.term_of_node(node),
                     &Term::Cons(Cons::PointerType(PointerType {
-                        of: Box::new(self.astNode2Term(expr)),
+                        of: Box::new(self.term_of_node(expr)),
                     })),
+                    node,
                 );
             }
             AstNodeKind::Ref(Ref { ref id }) => {
-                self.union_find.union(
-                    &self.astNode2Term(node),
+                self.unify(
+                    &self.term_of_node(node),
                     &Term::Cons(Cons::PointerType(PointerType {
-                        of: Box::new(self.astNode2Term(id)),
+                        of: Box::new(self.term_of_node(id)),
                     })),
+                    node,
                 );
             }
             AstNodeKind::Deref(Deref { ref atom }) => {
-                self.union_find.union(
-                    &self.astNode2Term(atom),
+                self.unify(
+                    &self.term_of_node(atom),
                     &Term::Cons(Cons::PointerType(PointerType {
-                        of: Box::new(self.astNode2Term(node)),
+                        of: Box::new(self.term_of_node(node)),
                     })),
+                    node,
                 );
             }
             AstNodeKind::FunApp(FunApp {
@@ -184,22 +421,21 @@ impl Dfs for TypeAnalysis {
                 ref params,
             }) => {
                 let params_output: Vec<Term> =
-                    params.iter().map(|x| self.astNode2Term(x)).collect();
+                    params.iter().map(|x| self.term_of_node(x)).collect();
                 let ft = FunctionType {
                     params: params_output,
-                    ret: Box::new(Term::fresh_var()),
+                    ret: Box::new(self.supply.fresh()),
                 };
-                self.union_find.union(&self.astNode2Term(node), &ft.ret);
-                self.union_find.union(
-                    &self.astNode2Term(method),
+                self.unify(&self.term_of_node(node), &ft.ret, node);
+                self.unify(
+                    &self.term_of_node(method),
                     &Term::Cons(Cons::FunctionType(ft)),
+                    node,
                 );
             }
             AstNodeKind::FieldAccess(FieldAccess { ref name, ref path }) => {
-                let mut rec = self.new_record();
-                rec.fields.insert(path.to_string(), self.astNode2Term(node));
-                self.union_find
-                    .union(&self.astNode2Term(name), &Term::Cons(Cons::RecordType(rec)));
+                let rec = self.open_record(path, self.term_of_node(node));
+                self.unify(&self.term_of_node(name), &rec, node);
             }
             AstNodeKind::Expression(BinaryOp {
                 ref left,
@@ -210,19 +446,14 @@ impl Dfs for TypeAnalysis {
                     Op::Equal => {
                         // left=right
                         // node=Int
-                        self.union_find
-                            .union(&self.astNode2Term(left), &self.astNode2Term(right));
-                        self.union_find
-                            .union(&self.astNode2Term(node), &Term::Cons(Cons::IntType));
+                        self.unify(&self.term_of_node(left), &self.term_of_node(right), node);
+                        self.unify(&self.term_of_node(node), &Term::Cons(Cons::IntType), node);
                     }
                     _ => {
                         // left=right=node=Int
-                        self.union_find
-                            .union(&self.astNode2Term(left), &Term::Cons(Cons::IntType));
-                        self.union_find
-                            .union(&self.astNode2Term(right), &Term::Cons(Cons::IntType));
-                        self.union_find
-                            .union(&self.astNode2Term(node), &Term::Cons(Cons::IntType));
+                        self.unify(&self.term_of_node(left), &Term::Cons(Cons::IntType), node);
+                        self.unify(&self.term_of_node(right), &Term::Cons(Cons::IntType), node);
+                        self.unify(&self.term_of_node(node), &Term::Cons(Cons::IntType), node);
                     }
                 }
             }
@@ -231,22 +462,131 @@ impl Dfs for TypeAnalysis {
     }
 
     fn finish(self) -> Self::ResultType {
-        let env = self.union_find.solution();
-        let mut res: HashMap<Term, Term> = HashMap::new();
-        let mut fresh_vars = HashMap::<Term, Term>::new();
-        for (k, v) in &env {
-            if let Term::Var(Var::VarType(n)) = k {
-                if let AstNodeKind::Id(_) = n.kind {
-                    let x = close(v, &env, &mut fresh_vars);
-                    res.insert(k.clone(), x);
-                } else if let AstNodeKind::Function(_) = n.kind {
-                    let x = close(v, &env, &mut fresh_vars);
-                    res.insert(k.clone(), x);
+        self.solve().0
+    }
+}
+
+/// closed type of every expression, param, var (declaration) and AstNode::Function
+/// a type variable stands for any type
+#[derive(Debug)]
+pub struct TypeEnv {
+    types: HashMap<AstNode, Term>,
+}
+
+impl TypeEnv {
+    /// an Id has the type of its declaration
+    pub fn get(&self, node: &AstNode) -> Option<&Term> {
+        self.types.get(node)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&AstNode, &Term)> {
+        self.types.iter()
+    }
+}
+
+/// type inference of a program, see the TIP book
+#[derive(Debug)]
+pub struct TypeInference {
+    env: TypeEnv,
+    errors: Vec<TypeError>,
+    /// name => AstNode::Function
+    functions: HashMap<String, AstNode>,
+    /// the variables of the types come from here
+    supply: VarSupply,
+}
+
+impl TypeInference {
+    /// monomorphic: every use of a function has the same type
+    pub fn new(program: &AstNode) -> Self {
+        TypeAnalysis::work(program)
+    }
+
+    /// let-polymorphic (Hindley–Milner) inference
+    /// functions are generalized bottom-up, a function after every function it names,
+    /// each use of a function instantiates its type with fresh variables
+    /// functions of one SCC are monomorphic inside the SCC
+    pub fn polymorphic(program: &AstNode) -> Self {
+        let functions: HashMap<String, AstNode> = match program.kind {
+            AstNodeKind::Program(ref functions) => functions
+                .iter()
+                .map(|f| match f.kind {
+                    AstNodeKind::Function(Function { ref name, .. }) => (name.clone(), f.clone()),
+                    _ => unreachable!(),
+                })
+                .collect(),
+            _ => unreachable!(),
+        };
+        let mut res = Self {
+            env: TypeEnv {
+                types: HashMap::new(),
+            },
+            errors: vec![],
+            functions: functions.clone(),
+            supply: VarSupply::new(),
+        };
+        let mut schemes = HashMap::new();
+        let mut decl = DeclarationAnalysis::work(program);
+        for scc in CallGraph::with_references(program, &decl).sccs() {
+            let mut analysis = TypeAnalysis::with_decl(std::mem::take(&mut decl));
+            analysis.schemes = schemes.clone();
+            // instances and schemes never share a variable
+            analysis.supply = std::mem::take(&mut res.supply);
+            for name in &scc {
+                analysis.dfs(&functions[name]);
+            }
+            let (part, rest) = analysis.solve();
+            decl = rest;
+            for name in &scc {
+                if let Some(t) = part.type_of_function(name) {
+                    schemes.insert(functions[name].clone(), t.clone());
                 }
             }
+            res.env.types.extend(part.env.types);
+            res.errors.extend(part.errors);
+            res.supply = part.supply;
         }
         res
     }
+
+    pub fn env(&self) -> &TypeEnv {
+        &self.env
+    }
+
+    /// in the order of the constraints
+    pub fn errors(&self) -> &[TypeError] {
+        &self.errors
+    }
+
+    pub fn type_of(&self, node: &AstNode) -> Option<&Term> {
+        self.env.get(node)
+    }
+
+    pub fn type_of_function(&self, name: &str) -> Option<&Term> {
+        self.env.get(self.functions.get(name)?)
+    }
+
+    /// a param or var of a function
+    pub fn type_of_var(&self, function: &str, name: &str) -> Option<&Term> {
+        match self.functions.get(function)?.kind {
+            AstNodeKind::Function(Function {
+                ref params,
+                ref vars,
+                ..
+            }) => {
+                let x = params.iter().chain(vars).find(|x| match x.kind {
+                    AstNodeKind::Id(ref id) => id == name,
+                    _ => false,
+                })?;
+                self.env.get(x)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// every type error of a program, in the order of the constraints
+pub fn type_errors(program: &AstNode) -> Vec<TypeError> {
+    TypeInference::new(program).errors
 }
 
 /// fresh_vars: Var => FreshVarType
@@ -254,10 +594,11 @@ fn close_rec(
     t: &Term,
     env: &HashMap<Term, Term>,
     fresh_vars: &mut HashMap<Term, Term>,
+    supply: &mut VarSupply,
     mut visited: HashSet<Term>,
 ) -> Term {
     match t {
-        Term::Var(var) => {
+        Term::Var(_) => {
             let (t_par, b) = match env.get(t) {
                 Some(t_par) => (Some(t_par), t_par != t),
                 None => (None, false),
@@ -265,11 +606,11 @@ fn close_rec(
             match (visited.get(t), b) {
                 (None, true) => {
                     visited.insert(t.clone());
-                    let cterm = close_rec(t_par.unwrap(), env, fresh_vars, visited);
+                    let cterm = close_rec(t_par.unwrap(), env, fresh_vars, supply, visited);
                     if let Some(f) = fresh_vars.get(t) {
                         if let Term::Cons(ref c) = cterm {
                             if c.contain(f) {
-                                if let Term::Var(v) = f {
+                                if let Term::Var(_) = f {
                                     let x = cterm.substitute(t, f);
                                     return Term::Mu(Mu::RecursiveType(RecursiveType {
                                         v: Box::new(f.clone()),
@@ -286,7 +627,7 @@ fn close_rec(
                 _ => match fresh_vars.get(t) {
                     Some(res) => res.clone(),
                     None => {
-                        fresh_vars.insert(t.clone(), Term::fresh_var());
+                        fresh_vars.insert(t.clone(), supply.fresh());
                         fresh_vars.get(t).unwrap().clone()
                     }
                 },
@@ -298,94 +639,243 @@ fn close_rec(
             Cons::FunctionType(ft) => {
                 let mut params = vec![];
                 for p in &ft.params {
-                    params.push(close_rec(p, env, fresh_vars, visited.clone()));
+                    params.push(close_rec(p, env, fresh_vars, supply, visited.clone()));
                 }
                 Term::Cons(Cons::FunctionType(FunctionType {
                     params,
-                    ret: Box::new(close_rec(&ft.ret, env, fresh_vars, visited)),
+                    ret: Box::new(close_rec(&ft.ret, env, fresh_vars, supply, visited)),
                 }))
             }
             Cons::PointerType(PointerType { ref of }) => {
                 let pt_clone = PointerType {
-                    of: Box::new(close_rec(of, env, fresh_vars, visited)),
+                    of: Box::new(close_rec(of, env, fresh_vars, supply, visited)),
                 };
                 Term::Cons(Cons::PointerType(pt_clone))
             }
-            Cons::RecordType(RecordType { fields, .. }) => {
+            Cons::RecordType(RecordType { fields, rest }) => {
                 let mut res = RecordType::new();
                 for (k, v) in fields {
                     res.fields.insert(
                         k.to_string(),
-                        close_rec(v, env, fresh_vars, visited.clone()),
+                        close_rec(v, env, fresh_vars, supply, visited.clone()),
                     );
                 }
+                // the row is flattened into the fields it is bound to
+                if let Some(rest) = rest {
+                    match close_rec(rest, env, fresh_vars, supply, visited) {
+                        Term::Cons(Cons::RecordType(r)) => {
+                            res.fields.extend(r.fields);
+                            res.rest = r.rest;
+                        }
+                        t => res.rest = Some(Box::new(t)),
+                    }
+                }
                 Term::Cons(Cons::RecordType(res))
             }
         },
         Term::Mu(Mu::RecursiveType(RecursiveType { v, t })) => {
             Term::Mu(Mu::RecursiveType(RecursiveType {
                 v: v.clone(),
-                t: Box::new(close_rec(t, env, fresh_vars, visited)),
+                t: Box::new(close_rec(t, env, fresh_vars, supply, visited)),
             }))
         }
     }
 }
 
-fn close(t: &Term, env: &HashMap<Term, Term>, fresh_vars: &mut HashMap<Term, Term>) -> Term {
-    close_rec(t, env, fresh_vars, HashSet::new())
+fn close(
+    t: &Term,
+    env: &HashMap<Term, Term>,
+    fresh_vars: &mut HashMap<Term, Term>,
+    supply: &mut VarSupply,
+) -> Term {
+    close_rec(t, env, fresh_vars, supply, HashSet::new())
 }
 
 #[cfg(test)]
 mod tests {
     use crate::ast_parser::parse;
-    use crate::dfs::Dfs;
     use crate::term::Mu;
     use crate::term::{FunctionType, PointerType, RecordType, RecursiveType};
-    use crate::type_analysis::AstNode;
-    use crate::type_analysis::AstNodeKind;
+    use crate::type_analysis::type_errors;
     use crate::type_analysis::Cons;
-    use crate::type_analysis::Function;
     use crate::type_analysis::Term;
-    use crate::type_analysis::TypeAnalysis;
-    use crate::type_analysis::Var;
-    use std::alloc::System;
-    use std::collections::HashMap;
+    use crate::type_analysis::TypeInference;
     use std::fs;
 
-    fn get_functiontype_by_name<'a>(mp: &'a HashMap<Term, Term>, name: &str) -> &'a FunctionType {
-        let t = mp
-            .iter()
-            .filter(|(k, v)| {
-                if let Term::Var(Var::VarType(AstNode {
-                    kind: AstNodeKind::Function(f),
-                    ..
-                })) = k
-                {
-                    if f.name == name {
-                        true
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                }
-            })
-            .map(|(_k, v)| v)
-            .next()
-            .unwrap();
-        if let Term::Cons(Cons::FunctionType(f)) = t {
+    fn get_functiontype_by_name<'a>(res: &'a TypeInference, name: &str) -> &'a FunctionType {
+        if let Some(Term::Cons(Cons::FunctionType(f))) = res.type_of_function(name) {
             f
         } else {
             unreachable!();
         }
     }
 
+    #[test]
+    fn test_type_errors() {
+        let program = parse("main() { var x, y; x = 1; y = *x; output alloc 1; return y; }");
+        let errors: Vec<String> = type_errors(&program)
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "1:31: cannot unify int with a pointer: only a pointer can be dereferenced \
+                 (int from 1:24, a pointer from 1:31)",
+                "1:42: cannot unify int with a pointer: \
+                 the value of this expression does not match its uses \
+                 (int from 1:35, a pointer from 1:42)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotations() {
+        let program = parse(
+            "inc(n: int): int { return n + 1; }
+             get(p: &int): int { var q: int; q = p; return *q; }
+             main() { var r: {a: int}; r = {a: 1}; return inc(r.a); }",
+        );
+        let res = TypeInference::new(&program);
+        assert_eq!(
+            res.type_of_function("inc").unwrap().to_string(),
+            "(int) -> int"
+        );
+        // q is dereferenced, so it can't be an int
+        let errors: Vec<String> = res.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "2:38: cannot unify a pointer with int: \
+                 the declared type does not match the inferred type \
+                 (a pointer from 2:60, int from 2:38)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_open_records() {
+        let program = parse(
+            "getx(p) { return (*p).x; }
+             main() { var a, b; a = {x: 1, y: 2}; b = {x: 3}; return getx(&a) + getx(&b) + b.y; }",
+        );
+        let res = TypeInference::polymorphic(&program);
+        assert_eq!(
+            res.type_of_function("getx").unwrap().to_string(),
+            "(↑{x: α | β}) -> α"
+        );
+        assert_eq!(
+            res.type_of_var("main", "a").unwrap().to_string(),
+            "{x: int, y: int}"
+        );
+        // each call has its own instance, only b.y reads a missing field
+        let errors: Vec<String> = res.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "2:92: cannot unify a record {x} with a record {y, ..}: \
+                 the field `y` is missing (a record {x} from 2:55, a record {y, ..} from 2:92)"
+            ]
+        );
+        let errors: Vec<String> = TypeInference::new(&program)
+            .errors()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        // monomorphic: the second call passes a record without y
+        assert_eq!(
+            errors[0],
+            "2:86: cannot unify a record {x, y} with a record {x}: \
+             the field `y` is missing (a record {x, y} from 2:37, a record {x} from 2:55)"
+        );
+    }
+
+    #[test]
+    fn test_type_inference() {
+        let program = parse(
+            "inc(p) { *p = *p + 1; return p; }
+             main() { var x, r; x = 1; r = {a: inc(&x)}; return *(r.a); }",
+        );
+        let res = TypeInference::new(&program);
+        let int = Term::Cons(Cons::IntType);
+        let pointer = Term::from(PointerType {
+            of: Box::new(int.clone()),
+        });
+        assert_eq!(
+            res.type_of_function("inc"),
+            Some(&Term::from(FunctionType {
+                params: vec![pointer.clone()],
+                ret: Box::new(pointer.clone()),
+            }))
+        );
+        assert_eq!(res.type_of_var("main", "x"), Some(&int));
+        let mut record = RecordType::new();
+        record.fields.insert("a".to_string(), pointer.clone());
+        assert_eq!(res.type_of_var("main", "r"), Some(&Term::from(record)));
+        assert_eq!(res.type_of_var("main", "y"), None);
+        // a call and a field access are typed too
+        let type_of = |expr: &str| {
+            res.env()
+                .iter()
+                .find(|(node, _)| node.to_string() == expr)
+                .map(|(_, t)| t.clone())
+        };
+        assert_eq!(type_of("inc(&x)"), Some(pointer.clone()));
+        assert_eq!(type_of("r.a"), Some(pointer));
+        let (node, _) = res
+            .env()
+            .iter()
+            .find(|(n, _)| n.to_string() == "x")
+            .unwrap();
+        assert_eq!(res.type_of(node), Some(&int));
+        assert!(res.errors().is_empty());
+    }
+
+    #[test]
+    fn test_polymorphic() {
+        let program = parse(
+            "id(x) { return x; }
+             main() { var a, b; a = id(1); b = id(&a); return *b; }",
+        );
+        // a and &a are passed to the same param
+        assert!(!TypeInference::new(&program).errors().is_empty());
+
+        let res = TypeInference::polymorphic(&program);
+        assert!(res.errors().is_empty());
+        match res.type_of_function("id") {
+            Some(Term::Cons(Cons::FunctionType(FunctionType { params, ret }))) => {
+                assert!(matches!(params[0], Term::Var(_)));
+                assert_eq!(&params[0], ret.as_ref());
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            res.type_of_var("main", "b"),
+            Some(&Term::from(PointerType {
+                of: Box::new(Term::Cons(Cons::IntType))
+            }))
+        );
+    }
+
+    #[test]
+    fn test_fresh_var_names() {
+        let program = parse("id(x) { return x; }");
+        // the same names on every run, whatever ran before
+        for _ in 0..2 {
+            let res = TypeInference::new(&program);
+            assert_eq!(
+                format!("{:?}", res.type_of_function("id").unwrap()),
+                "(x1)->x1"
+            );
+        }
+    }
+
     #[test]
     fn test_foo_type() -> std::io::Result<()> {
         let path = "/home/lyj/TIP/examples/foo.tip";
-        let content = fs::read_to_string(&path)?;
+        let content = fs::read_to_string(path)?;
         let program = parse(&content);
-        let res = TypeAnalysis::work(&program);
+        let res = TypeInference::new(&program);
         let foo = get_functiontype_by_name(&res, "foo");
         assert_eq!(&foo.ret as &Term, &Term::Cons(Cons::IntType));
         assert_eq!(
@@ -425,9 +915,9 @@ mod tests {
         // let path = "/home/lyj/TIP/examples/map.tip";
         // let path = "/home/lyj/TIP/examples/record5.tip";
         // let path = "/home/lyj/TIP/examples/record4.tip";
-        let content = fs::read_to_string(&path)?;
+        let content = fs::read_to_string(path)?;
         let program = parse(&content);
-        let res = TypeAnalysis::work(&program);
+        let res = TypeInference::new(&program);
         dbg!(res);
         Ok(())
     }
@@ -438,10 +928,10 @@ mod tests {
             let entry = entry?;
             let path = entry.path();
             if path.is_file() {
-                let content = &fs::read_to_string(&path)?;
+                let content = fs::read_to_string(&path)?;
                 dbg!(&path);
                 let program = parse(&content);
-                let res = TypeAnalysis::work(&program);
+                TypeInference::new(&program);
             }
         }
         Ok(())
@@ -0,0 +1,391 @@
+use crate::term::*;
+use std::collections::{HashMap, HashSet};
+
+/// decide if two types are the same regular tree,
+/// modulo μ-unfolding and a consistent renaming of their variables
+/// absent fields of a record are ignored
+pub fn equivalent(t1: &Term, t2: &Term) -> bool {
+    Bisimulation::default().check(t1, t2)
+}
+
+/// pairs of types assumed equal, and the renaming of variables found so far
+#[derive(Default)]
+struct Bisimulation {
+    assumed: HashSet<(Term, Term)>,
+    left: HashMap<Term, Term>,
+    right: HashMap<Term, Term>,
+}
+
+impl Bisimulation {
+    /// a pair met again is equal, unless some other pair says otherwise
+    fn check(&mut self, t1: &Term, t2: &Term) -> bool {
+        let (t1, t2) = match (unfold(t1), unfold(t2)) {
+            (Some(t1), Some(t2)) => (t1, t2),
+            // an unguarded μα.α is only equal to another one
+            (None, None) => return true,
+            _ => return false,
+        };
+        if !self.assumed.insert((t1.clone(), t2.clone())) {
+            return true;
+        }
+        match (&t1, &t2) {
+            (Term::Var(_), Term::Var(_)) => {
+                self.left.entry(t1.clone()).or_insert_with(|| t2.clone()) == &t2
+                    && self.right.entry(t2.clone()).or_insert_with(|| t1.clone()) == &t1
+            }
+            (Term::Cons(c1), Term::Cons(c2)) => match (c1, c2) {
+                (Cons::IntType, Cons::IntType) | (Cons::AbsentFieldType, Cons::AbsentFieldType) => {
+                    true
+                }
+                (Cons::PointerType(p1), Cons::PointerType(p2)) => self.check(&p1.of, &p2.of),
+                (Cons::FunctionType(f1), Cons::FunctionType(f2))
+                    if f1.params.len() == f2.params.len() =>
+                {
+                    f1.params
+                        .iter()
+                        .zip(f2.params.iter())
+                        .all(|(p1, p2)| self.check(p1, p2))
+                        && self.check(&f1.ret, &f2.ret)
+                }
+                (Cons::RecordType(r1), Cons::RecordType(r2)) => {
+                    let (k1, k2) = (present(r1), present(r2));
+                    k1 == k2
+                        && k1.iter().all(|k| self.check(&r1.fields[k], &r2.fields[k]))
+                        && match (&r1.rest, &r2.rest) {
+                            (Some(rest1), Some(rest2)) => self.check(rest1, rest2),
+                            (None, None) => true,
+                            _ => false,
+                        }
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+/// the type with the μs at its root unfolded
+/// None for an unguarded μα.α, whose unfoldings never reach a constructor or a variable
+fn unfold(t: &Term) -> Option<Term> {
+    let mut t = t.clone();
+    let mut seen = HashSet::new();
+    while let Term::Mu(Mu::RecursiveType(r)) = &t {
+        if !seen.insert(t.clone()) {
+            return None;
+        }
+        t = r.unfold();
+    }
+    Some(t)
+}
+
+/// the fields of a record which are not absent, sorted
+fn present(r: &RecordType) -> Vec<String> {
+    let mut keys: Vec<String> = r
+        .fields
+        .iter()
+        .filter(|(_, t)| !matches!(t, Term::Cons(Cons::AbsentFieldType)))
+        .map(|(k, _)| k.clone())
+        .collect();
+    keys.sort();
+    keys
+}
+
+/// the constructor of a state of the automaton of a type
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+enum Label {
+    Int,
+    Pointer,
+    /// number of params
+    Function(usize),
+    /// names of the present fields, sorted, and if there is a row variable after them
+    Record(Vec<String>, bool),
+    /// a free variable, or an unguarded μα.α kept as is
+    Leaf(Term),
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    label: Label,
+    succ: Vec<usize>,
+}
+
+/// a type as a finite automaton: one state per distinct subterm, μ-unfolding gives the back edges
+#[derive(Default)]
+struct Automaton {
+    states: Vec<State>,
+    memo: HashMap<Term, usize>,
+    /// state of μα.τ => state of its unfolding, which may be unfinished when the μ is built
+    aliases: HashMap<usize, usize>,
+}
+
+impl Automaton {
+    fn build(&mut self, t: &Term) -> usize {
+        if let Some(&s) = self.memo.get(t) {
+            return s;
+        }
+        let s = self.states.len();
+        self.states.push(State {
+            label: Label::Leaf(t.clone()),
+            succ: vec![],
+        });
+        self.memo.insert(t.clone(), s);
+        let (label, children): (Label, Vec<&Term>) = match t {
+            Term::Var(_) | Term::Cons(Cons::AbsentFieldType) => return s,
+            Term::Cons(Cons::IntType) => (Label::Int, vec![]),
+            Term::Cons(Cons::PointerType(PointerType { of })) => (Label::Pointer, vec![of]),
+            Term::Cons(Cons::FunctionType(FunctionType { params, ret })) => (
+                Label::Function(params.len()),
+                params.iter().chain(std::iter::once(ret.as_ref())).collect(),
+            ),
+            Term::Cons(Cons::RecordType(r)) => {
+                let keys = present(r);
+                let children = keys
+                    .iter()
+                    .map(|k| &r.fields[k])
+                    .chain(r.rest.as_deref())
+                    .collect();
+                (Label::Record(keys, r.rest.is_some()), children)
+            }
+            Term::Mu(Mu::RecursiveType(r)) => {
+                let b = self.build(&r.unfold());
+                self.aliases.insert(s, b);
+                return s;
+            }
+        };
+        let succ = children.into_iter().map(|c| self.build(c)).collect();
+        self.states[s] = State { label, succ };
+        s
+    }
+
+    /// the state a state stands for, after the μs on the way
+    /// a cycle of aliases is an unguarded μα.α, which stays a leaf
+    fn target(&self, s: usize) -> usize {
+        let mut t = s;
+        let mut seen = HashSet::new();
+        while let Some(&next) = self.aliases.get(&t) {
+            if !seen.insert(t) {
+                return s;
+            }
+            t = next;
+        }
+        t
+    }
+
+    /// replace every edge to a μ by an edge to its unfolding
+    fn resolve(&mut self) {
+        for i in 0..self.states.len() {
+            let succ = self.states[i]
+                .succ
+                .iter()
+                .map(|&s| self.target(s))
+                .collect();
+            self.states[i].succ = succ;
+        }
+    }
+
+    /// Moore's partition refinement: the class of every state in the minimal automaton
+    fn classes(&self) -> Vec<usize> {
+        let mut class: Vec<usize> = vec![0; self.states.len()];
+        let mut count = 0;
+        loop {
+            let mut ids: HashMap<(Label, Vec<usize>), usize> = HashMap::new();
+            let next: Vec<usize> = self
+                .states
+                .iter()
+                .enumerate()
+                .map(|(i, state)| {
+                    let key = (
+                        state.label.clone(),
+                        std::iter::once(class[i])
+                            .chain(state.succ.iter().map(|&s| class[s]))
+                            .collect(),
+                    );
+                    let id = ids.len();
+                    *ids.entry(key).or_insert(id)
+                })
+                .collect();
+            class = next;
+            if ids.len() == count {
+                return class;
+            }
+            count = ids.len();
+        }
+    }
+}
+
+/// the canonical form of a type: the tree of its minimal automaton,
+/// with a μ at the first state of each cycle met from the root
+/// equivalent types with the same free variables are minimized to equal terms
+pub fn minimize(t: &Term) -> Term {
+    let mut automaton = Automaton::default();
+    let root = automaton.build(t);
+    automaton.resolve();
+    let root = automaton.target(root);
+    let class = automaton.classes();
+    let mut reps = HashMap::new();
+    for (s, &c) in class.iter().enumerate() {
+        reps.entry(c).or_insert(s);
+    }
+    // binders are numbered after the free variables, so they can't capture one
+    let next = t
+        .free_vars()
+        .iter()
+        .filter_map(|v| match v {
+            Term::Var(Var::FreshVarType(i)) => Some(*i),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let mut reader = Reader {
+        automaton: &automaton,
+        class,
+        reps,
+        stack: vec![],
+        binders: HashMap::new(),
+        next,
+    };
+    reader.read(root)
+}
+
+/// turn the minimal automaton back into a term
+struct Reader<'a> {
+    automaton: &'a Automaton,
+    class: Vec<usize>,
+    /// class => its first state
+    reps: HashMap<usize, usize>,
+    /// classes on the path from the root
+    stack: Vec<usize>,
+    /// class on the path => the variable of its μ, if a back edge reaches it
+    binders: HashMap<usize, Term>,
+    next: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, s: usize) -> Term {
+        let c = self.class[s];
+        if self.stack.contains(&c) {
+            let next = &mut self.next;
+            return self
+                .binders
+                .entry(c)
+                .or_insert_with(|| {
+                    *next += 1;
+                    Term::Var(Var::FreshVarType(*next))
+                })
+                .clone();
+        }
+        let state = &self.automaton.states[self.reps[&c]];
+        self.stack.push(c);
+        let mut children: Vec<Term> = state.succ.iter().map(|&s| self.read(s)).collect();
+        self.stack.pop();
+        let t = match state.label {
+            Label::Int => Term::Cons(Cons::IntType),
+            Label::Pointer => Term::from(PointerType {
+                of: Box::new(children.remove(0)),
+            }),
+            Label::Function(_) => {
+                let ret = children.pop().unwrap();
+                Term::from(FunctionType {
+                    params: children,
+                    ret: Box::new(ret),
+                })
+            }
+            Label::Record(ref keys, open) => {
+                let mut r = RecordType::new();
+                if open {
+                    r.rest = children.pop().map(Box::new);
+                }
+                r.fields = keys.iter().cloned().zip(children).collect();
+                Term::from(r)
+            }
+            Label::Leaf(ref t) => t.clone(),
+        };
+        match self.binders.remove(&c) {
+            Some(v) => Term::Mu(Mu::RecursiveType(RecursiveType {
+                v: Box::new(v),
+                t: Box::new(t),
+            })),
+            None => t,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::term::*;
+    use crate::type_equivalence::{equivalent, minimize};
+
+    fn pointer(of: Term) -> Term {
+        Term::from(PointerType { of: Box::new(of) })
+    }
+
+    fn mu(v: &Term, t: Term) -> Term {
+        Term::Mu(Mu::RecursiveType(RecursiveType {
+            v: Box::new(v.clone()),
+            t: Box::new(t),
+        }))
+    }
+
+    #[test]
+    fn test_equivalent() {
+        let mut supply = VarSupply::new();
+        let (a, b, x, y) = (
+            supply.fresh(),
+            supply.fresh(),
+            supply.fresh(),
+            supply.fresh(),
+        );
+        // μa.↑↑a, μb.↑b and ↑μa.↑a are the same infinite chain of pointers
+        let twice = mu(&a, pointer(pointer(a.clone())));
+        let once = mu(&b, pointer(b.clone()));
+        assert!(equivalent(&twice, &once));
+        assert!(equivalent(&pointer(once.clone()), &once));
+        assert!(!equivalent(&once, &pointer(Term::Cons(Cons::IntType))));
+        // μa.a has no constructor to compare
+        let int = Term::Cons(Cons::IntType);
+        assert!(!equivalent(&mu(&a, a.clone()), &int));
+        assert!(!equivalent(&int, &mu(&a, a.clone())));
+        assert!(equivalent(&mu(&a, a.clone()), &mu(&b, b.clone())));
+        // free variables are renamed consistently
+        let f = |p: &Term, q: &Term| {
+            Term::from(FunctionType {
+                params: vec![p.clone(), q.clone()],
+                ret: Box::new(p.clone()),
+            })
+        };
+        assert!(equivalent(&f(&a, &b), &f(&x, &y)));
+        assert!(!equivalent(&f(&a, &b), &f(&x, &x)));
+        // absent fields don't count
+        let mut r1 = RecordType::new();
+        r1.fields.insert("v".to_string(), x.clone());
+        let mut r2 = r1.clone();
+        r2.fields
+            .insert("w".to_string(), Term::Cons(Cons::AbsentFieldType));
+        assert!(equivalent(&Term::from(r1), &Term::from(r2)));
+    }
+
+    #[test]
+    fn test_minimize() {
+        let mut supply = VarSupply::new();
+        let (a, b) = (supply.fresh(), supply.fresh());
+        let twice = mu(&a, pointer(pointer(a.clone())));
+        let once = pointer(mu(&b, pointer(b.clone())));
+        assert_eq!(minimize(&twice), minimize(&once));
+        assert_eq!(minimize(&twice).to_string(), "μα.↑α");
+        // μ without a use of its variable goes away, the free variables stay
+        let f = mu(
+            &a,
+            Term::from(FunctionType {
+                params: vec![b.clone()],
+                ret: Box::new(mu(&a, pointer(b.clone()))),
+            }),
+        );
+        assert_eq!(
+            minimize(&f),
+            Term::from(FunctionType {
+                params: vec![b.clone()],
+                ret: Box::new(pointer(b)),
+            })
+        );
+    }
+}
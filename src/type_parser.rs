@@ -0,0 +1,145 @@
+use crate::ast_parser::{IdentParser, Rule};
+use crate::term::*;
+use pest::iterators::Pair;
+use pest::Parser;
+use std::collections::HashMap;
+
+/// parse a type written as by TypePrinter, e.g. `{next: ↑μt.{v: int, next: ↑t}}`
+/// `&τ` and `mu a.τ` can be written instead of `↑τ` and `μa.τ`
+/// every type variable becomes a fresh variable, the same name the same variable
+pub fn parse_type(input: &str) -> Term {
+    let pair = IdentParser::parse(Rule::type_input, input)
+        .unwrap_or_else(|e| panic!("{}", e))
+        .next()
+        .unwrap();
+    TypeBuilder::new().build(pair)
+}
+
+/// turn a Rule::type_expr into a Term
+/// the names of type variables are shared by every type built by the same builder
+pub(crate) struct TypeBuilder {
+    supply: VarSupply,
+    /// name => Term::Var, a μ hides the variables of the same name while it is built
+    names: HashMap<String, Term>,
+}
+
+impl TypeBuilder {
+    pub fn new() -> Self {
+        Self {
+            supply: VarSupply::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    pub fn build(&mut self, pair: Pair<Rule>) -> Term {
+        match pair.as_rule() {
+            Rule::type_expr => self.build(pair.into_inner().next().unwrap()),
+            Rule::int_type => Term::Cons(Cons::IntType),
+            Rule::type_var => {
+                let name = pair.as_str().to_string();
+                let supply = &mut self.supply;
+                self.names
+                    .entry(name)
+                    .or_insert_with(|| supply.fresh())
+                    .clone()
+            }
+            Rule::pointer_type => Term::from(PointerType {
+                of: Box::new(self.build(pair.into_inner().next().unwrap())),
+            }),
+            Rule::function_type => {
+                let mut params: Vec<Term> = pair.into_inner().map(|p| self.build(p)).collect();
+                let ret = params.pop().unwrap();
+                Term::from(FunctionType {
+                    params,
+                    ret: Box::new(ret),
+                })
+            }
+            Rule::record_type => {
+                let mut r = RecordType::new();
+                for field in pair.into_inner() {
+                    if field.as_rule() == Rule::type_var {
+                        r.rest = Some(Box::new(self.build(field)));
+                        continue;
+                    }
+                    let mut field = field.into_inner();
+                    let name = field.next().unwrap().as_str().to_string();
+                    let t = self.build(field.next().unwrap());
+                    r.fields.insert(name, t);
+                }
+                Term::from(r)
+            }
+            Rule::mu_type => {
+                let mut pair = pair.into_inner();
+                let name = pair.next().unwrap().as_str().to_string();
+                let v = self.supply.fresh();
+                let hidden = self.names.insert(name.clone(), v.clone());
+                let t = self.build(pair.next().unwrap());
+                match hidden {
+                    Some(hidden) => self.names.insert(name, hidden),
+                    None => self.names.remove(&name),
+                };
+                Term::Mu(Mu::RecursiveType(RecursiveType {
+                    v: Box::new(v),
+                    t: Box::new(t),
+                }))
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::parse;
+    use crate::term::*;
+    use crate::type_analysis::TypeInference;
+    use crate::type_equivalence::equivalent;
+    use crate::type_parser::parse_type;
+    use crate::type_printer::TypePrinter;
+
+    #[test]
+    fn test_parse_type() {
+        let foo = parse_type("(↑int, μa.(↑int,a)->int) -> int");
+        assert_eq!(foo.to_string(), "(↑int,μα.(↑int,α) -> int) -> int");
+        assert_eq!(
+            foo,
+            parse_type("(&int, mu a.(&int, a) -> int) -> int"),
+            "the variables are numbered in the same order"
+        );
+        let list = parse_type("{next: ↑μt.{v:int, next:↑t}}");
+        assert_eq!(list.to_string(), "{next: ↑μα.{next: ↑α, v: int}}");
+        // what TypePrinter prints is parsed back
+        let rows = parse_type("(&{x: a | r}) -> a");
+        assert_eq!(rows.to_string(), "(↑{x: α | β}) -> α");
+        for t in &[foo, list, rows, parse_type("↑((a, integer) -> a)")] {
+            let printed = TypePrinter::ascii().print(t);
+            assert_eq!(&parse_type(&printed), t, "{}", printed);
+        }
+        assert_eq!(
+            parse_type("mu a.&a"),
+            Term::Mu(Mu::RecursiveType(RecursiveType {
+                v: Box::new(Term::Var(Var::FreshVarType(1))),
+                t: Box::new(Term::from(PointerType {
+                    of: Box::new(Term::Var(Var::FreshVarType(1))),
+                })),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_compare_inferred_types() {
+        let program = parse(
+            "foo(p, f) { var r; r = f(p, f); return *p + r; }
+             main() { var n; n = 1; return foo(&n, foo); }",
+        );
+        let res = TypeInference::new(&program);
+        assert!(equivalent(
+            res.type_of_function("foo").unwrap(),
+            &parse_type("(↑int, μa.(↑int,a)->int) -> int")
+        ));
+        assert!(equivalent(
+            res.type_of_function("main").unwrap(),
+            &parse_type("() -> int")
+        ));
+    }
+}
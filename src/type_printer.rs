@@ -0,0 +1,189 @@
+use crate::term::*;
+use std::collections::HashMap;
+use std::fmt;
+
+const GREEK: [&str; 23] = [
+    "α", "β", "γ", "δ", "ε", "ζ", "η", "θ", "ι", "κ", "λ", "ν", "ξ", "ο", "π", "ρ", "σ", "τ", "υ",
+    "φ", "χ", "ψ", "ω",
+];
+
+const LATIN: [&str; 26] = [
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p", "q", "r", "s",
+    "t", "u", "v", "w", "x", "y", "z",
+];
+
+/// print a type in the notation of the TIP book:
+/// `int`, `↑τ`, `(τ1,τ2) -> τ`, `{f: τ}` and `μα.τ`,
+/// `{f: τ | ρ}` is a record with the field f and the other fields of the row ρ
+/// type variables are renamed α, β, .. in order of appearance,
+/// so equal types up to the names of their variables print the same
+/// fields are sorted, absent fields are left out
+/// the body of μ extends as far right as possible
+pub struct TypePrinter {
+    /// `&τ`, `mu a.τ` and a, b, .. instead
+    ascii: bool,
+}
+
+impl TypePrinter {
+    pub fn new() -> Self {
+        Self { ascii: false }
+    }
+
+    pub fn ascii() -> Self {
+        Self { ascii: true }
+    }
+
+    pub fn print(&self, t: &Term) -> String {
+        self.show(t, &mut HashMap::new())
+    }
+
+    /// the types share their variables, e.g. the annotations of a function
+    pub fn print_all(&self, types: &[&Term]) -> Vec<String> {
+        let mut names = HashMap::new();
+        types.iter().map(|t| self.show(t, &mut names)).collect()
+    }
+
+    fn name(&self, t: &Term, names: &mut HashMap<Term, String>) -> String {
+        let letters: &[&str] = if self.ascii { &LATIN } else { &GREEK };
+        let i = names.len();
+        names
+            .entry(t.clone())
+            .or_insert_with(|| match i / letters.len() {
+                0 => letters[i].to_string(),
+                n => format!("{}{}", letters[i % letters.len()], n),
+            })
+            .clone()
+    }
+
+    fn show(&self, t: &Term, names: &mut HashMap<Term, String>) -> String {
+        match t {
+            Term::Var(_) => self.name(t, names),
+            Term::Cons(Cons::IntType) => "int".to_string(),
+            Term::Cons(Cons::PointerType(PointerType { of })) => {
+                let arrow = if self.ascii { "&" } else { "↑" };
+                match of.as_ref() {
+                    Term::Cons(Cons::FunctionType(_)) => {
+                        format!("{}({})", arrow, self.show(of, names))
+                    }
+                    _ => format!("{}{}", arrow, self.show(of, names)),
+                }
+            }
+            Term::Cons(Cons::FunctionType(FunctionType { params, ret })) => {
+                let params: Vec<String> = params.iter().map(|p| self.show(p, names)).collect();
+                format!("({}) -> {}", params.join(","), self.show(ret, names))
+            }
+            Term::Cons(Cons::RecordType(RecordType { fields, rest })) => {
+                let mut keys: Vec<&String> = fields
+                    .iter()
+                    .filter(|(_, t)| !matches!(t, Term::Cons(Cons::AbsentFieldType)))
+                    .map(|(k, _)| k)
+                    .collect();
+                keys.sort();
+                let fields: Vec<String> = keys
+                    .into_iter()
+                    .map(|k| format!("{}: {}", k, self.show(&fields[k], names)))
+                    .collect();
+                match rest {
+                    Some(rest) if fields.is_empty() => format!("{{| {}}}", self.show(rest, names)),
+                    Some(rest) => format!("{{{} | {}}}", fields.join(", "), self.show(rest, names)),
+                    None => format!("{{{}}}", fields.join(", ")),
+                }
+            }
+            Term::Cons(Cons::AbsentFieldType) => "◇".to_string(),
+            Term::Mu(Mu::RecursiveType(RecursiveType { v, t })) => {
+                let v = self.name(v, names);
+                if self.ascii {
+                    format!("mu {}.{}", v, self.show(t, names))
+                } else {
+                    format!("μ{}.{}", v, self.show(t, names))
+                }
+            }
+        }
+    }
+}
+
+impl Default for TypePrinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// the stable form of TypePrinter::new, e.g. for golden tests
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&TypePrinter::new().print(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::parse;
+    use crate::term::*;
+    use crate::type_analysis::TypeInference;
+    use crate::type_printer::TypePrinter;
+
+    #[test]
+    fn test_type_printer() {
+        let mut supply = VarSupply::new();
+        let int = Term::Cons(Cons::IntType);
+        let pointer = |of: Term| Term::from(PointerType { of: Box::new(of) });
+        // (↑int, μa.(↑int,a)->int) -> int
+        let a = supply.fresh();
+        let mu = Term::Mu(Mu::RecursiveType(RecursiveType {
+            v: Box::new(a.clone()),
+            t: Box::new(Term::from(FunctionType {
+                params: vec![pointer(int.clone()), a],
+                ret: Box::new(int.clone()),
+            })),
+        }));
+        let foo = Term::from(FunctionType {
+            params: vec![pointer(int.clone()), mu],
+            ret: Box::new(int.clone()),
+        });
+        assert_eq!(foo.to_string(), "(↑int,μα.(↑int,α) -> int) -> int");
+        assert_eq!(
+            TypePrinter::ascii().print(&foo),
+            "(&int,mu a.(&int,a) -> int) -> int"
+        );
+
+        // {next: ↑μt.{v: int, next: ↑t}}, with an absent field
+        let t = supply.fresh();
+        let mut list = RecordType::new();
+        list.fields.insert("v".to_string(), int.clone());
+        list.fields.insert("next".to_string(), pointer(t.clone()));
+        list.fields
+            .insert("key".to_string(), Term::Cons(Cons::AbsentFieldType));
+        let mut head = RecordType::new();
+        head.fields.insert(
+            "next".to_string(),
+            pointer(Term::Mu(Mu::RecursiveType(RecursiveType {
+                v: Box::new(t),
+                t: Box::new(Term::from(list)),
+            }))),
+        );
+        assert_eq!(
+            Term::from(head).to_string(),
+            "{next: ↑μα.{next: ↑α, v: int}}"
+        );
+
+        let f = Term::from(FunctionType {
+            params: vec![supply.fresh()],
+            ret: Box::new(int),
+        });
+        assert_eq!(pointer(f).to_string(), "↑((α) -> int)");
+    }
+
+    #[test]
+    fn test_print_inferred_types() {
+        let program = parse(
+            "id(x) { return x; }
+             swap(p, q) { var t; t = *p; *p = *q; *q = t; return 0; }",
+        );
+        let res = TypeInference::new(&program);
+        assert_eq!(res.type_of_function("id").unwrap().to_string(), "(α) -> α");
+        assert_eq!(
+            res.type_of_function("swap").unwrap().to_string(),
+            "(↑α,↑α) -> int"
+        );
+    }
+}
@@ -1,88 +1,320 @@
 use crate::ast_parser::AstNode;
 use crate::term::Cons;
+use crate::term::Mu;
+use crate::term::RecordType;
 use crate::term::Term;
+use crate::term::VarSupply;
 use std::collections::HashMap;
 
-pub struct UnionFindSolver(HashMap<Term, Term>);
+/// two constructors which can't be unified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clash {
+    pub left: Term,
+    pub right: Term,
+    /// the constraint which gave `left` to its class, see UnionFindSolver::union_at
+    pub left_origin: Option<AstNode>,
+    pub right_origin: Option<AstNode>,
+    /// two records clash on a field one of them has and the other can't have
+    pub field: Option<String>,
+}
+
+/// the origins of the two sides of a constraint
+type Origins = (Option<AstNode>, Option<AstNode>);
+
+/// the fields of r1 which r2 lacks, an absent field is not missing
+fn missing(r1: &RecordType, r2: &RecordType) -> Vec<String> {
+    let mut res: Vec<String> = r1
+        .fields
+        .iter()
+        .filter(|(k, t)| {
+            !r2.fields.contains_key(*k) && !matches!(t, Term::Cons(Cons::AbsentFieldType))
+        })
+        .map(|(k, _)| k.clone())
+        .collect();
+    res.sort();
+    res
+}
+
+pub struct UnionFindSolver {
+    parent: HashMap<Term, Term>,
+    /// Term::Var => the constraint which bound its class to a constructor
+    origins: HashMap<Term, AstNode>,
+}
 
 impl UnionFindSolver {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self {
+            parent: HashMap::new(),
+            origins: HashMap::new(),
+        }
     }
 
-    pub fn union(&mut self, k1: &Term, k2: &Term) {
+    /// the classes are still merged after a clash, every clash is returned
+    /// two open records with different extra fields clash, only union_at can give them a common row
+    pub fn union(&mut self, k1: &Term, k2: &Term) -> Vec<Clash> {
+        let mut clashes = vec![];
+        self.unify(k1, k2, None, (None, None), &mut clashes, None);
+        clashes
+    }
+
+    /// at: the node whose constraint is k1 = k2
+    /// unification goes on after a clash, so every clash is returned
+    /// supply: the variables of the analysis, two open records may need a new row variable
+    pub fn union_at(
+        &mut self,
+        k1: &Term,
+        k2: &Term,
+        at: &AstNode,
+        supply: &mut VarSupply,
+    ) -> Vec<Clash> {
+        let mut clashes = vec![];
+        let origins = (self.origin(k1, at), self.origin(k2, at));
+        self.unify(k1, k2, Some(at), origins, &mut clashes, Some(supply));
+        clashes
+    }
+
+    /// the constraint which gave a constructor to the class of `t`
+    /// a constructor is introduced by the constraint itself
+    fn origin(&mut self, t: &Term, at: &AstNode) -> Option<AstNode> {
+        match t {
+            Term::Var(_) => self.var_origin(t),
+            _ => Some(at.clone()),
+        }
+    }
+
+    fn unify(
+        &mut self,
+        k1: &Term,
+        k2: &Term,
+        at: Option<&AstNode>,
+        origins: Origins,
+        clashes: &mut Vec<Clash>,
+        mut supply: Option<&mut VarSupply>,
+    ) {
         let v1: Term = self.find(k1).clone();
         let v2: Term = self.find(k2).clone();
         if v1 == v2 {
             return;
         }
-        let v1_clone = v1.clone();
-        let v2_clone = v2.clone();
-        match (v1_clone, v2_clone) {
+        match (&v1, &v2) {
             (Term::Var(_), Term::Var(_)) => {
-                self.0.insert(v1, v2);
+                self.parent.insert(v1, v2);
             }
             (Term::Var(_), _) => {
-                self.0.insert(v1, v2);
+                self.bind(v1, v2, at);
             }
             (_, Term::Var(_)) => {
-                self.0.insert(v2, v1);
+                self.bind(v2, v1, at);
+            }
+            // μα.τ is the same type as τ[α := μα.τ]
+            (Term::Mu(Mu::RecursiveType(r)), _) => {
+                let t = r.unfold();
+                self.parent.insert(v1.clone(), v2.clone());
+                self.unify_sub(&t, &v2, at, &origins, clashes, supply);
+            }
+            (_, Term::Mu(Mu::RecursiveType(r))) => {
+                let t = r.unfold();
+                self.parent.insert(v2.clone(), v1.clone());
+                self.unify_sub(&v1, &t, at, &origins, clashes, supply);
             }
-            (Term::Cons(c1), Term::Cons(c2)) => {
-                self.0.insert(v1, v2);
-                match (c1, c2) {
-                    (Cons::IntType, Cons::IntType) => {}
-                    (Cons::FunctionType(f1), Cons::FunctionType(f2)) => {
-                        self.union(&f1.ret, &f2.ret);
-                        for (p1, p2) in f1.params.iter().zip(f2.params.iter()) {
-                            self.union(p1, p2);
-                        }
+            // a clash leaves both classes apart: int is shared by every class of integers
+            (Term::Cons(c1), Term::Cons(c2)) => match (c1, c2) {
+                (Cons::IntType, Cons::IntType) | (Cons::AbsentFieldType, Cons::AbsentFieldType) => {
+                    self.parent.insert(v1.clone(), v2.clone());
+                }
+                (Cons::FunctionType(f1), Cons::FunctionType(f2))
+                    if f1.params.len() == f2.params.len() =>
+                {
+                    self.parent.insert(v1.clone(), v2.clone());
+                    self.unify_sub(
+                        &f1.ret,
+                        &f2.ret,
+                        at,
+                        &origins,
+                        clashes,
+                        supply.as_deref_mut(),
+                    );
+                    for (p1, p2) in f1.params.iter().zip(f2.params.iter()) {
+                        self.unify_sub(p1, p2, at, &origins, clashes, supply.as_deref_mut());
                     }
-                    (Cons::PointerType(p1), Cons::PointerType(p2)) => {
-                        self.union(&p1.of, &p2.of);
+                }
+                (Cons::PointerType(p1), Cons::PointerType(p2)) => {
+                    self.parent.insert(v1.clone(), v2.clone());
+                    self.unify_sub(&p1.of, &p2.of, at, &origins, clashes, supply);
+                }
+                // a missing field is reported, the records are still merged
+                (Cons::RecordType(r1), Cons::RecordType(r2)) => {
+                    self.parent.insert(v1.clone(), v2.clone());
+                    self.unify_rows(r1, r2, at, &origins, clashes, supply);
+                    let mut fields = vec![];
+                    if r2.rest.is_none() {
+                        fields.extend(missing(r1, r2));
                     }
-                    (Cons::RecordType(r1), Cons::RecordType(r2)) => {
-                        assert_eq!(r1.fields.len(), r2.fields.len());
-                        for key in r1.fields.keys() {
-                            self.union(&r1.fields[key], &r2.fields[key]);
-                        }
+                    if r1.rest.is_none() {
+                        fields.extend(missing(r2, r1));
                     }
-                    (Cons::AbsentFieldType, Cons::AbsentFieldType) => {}
-                    (_, _) => {
-                        unreachable!();
+                    for field in fields {
+                        clashes.push(Clash {
+                            left: v1.clone(),
+                            right: v2.clone(),
+                            left_origin: origins.0.clone(),
+                            right_origin: origins.1.clone(),
+                            field: Some(field),
+                        });
                     }
                 }
-            }
-            // cons->mu
-            // mu->mu
-            (_, _) => {
-                unreachable!();
-            }
+                (_, _) => clashes.push(Clash {
+                    left: v1.clone(),
+                    right: v2.clone(),
+                    left_origin: origins.0,
+                    right_origin: origins.1,
+                    field: None,
+                }),
+            },
         };
     }
 
-    fn find(&mut self, key: &Term) -> &Term {
-        match self.0.get(key) {
-            Some(par) => {
-                if par == key {
-                    par
+    /// {F1 | ρ1} = {F2 | ρ2}: the common fields are unified,
+    /// the fields of one record the other lacks go to the row of the other
+    /// a field missing from a closed record is left to the caller
+    fn unify_rows(
+        &mut self,
+        r1: &RecordType,
+        r2: &RecordType,
+        at: Option<&AstNode>,
+        origins: &Origins,
+        clashes: &mut Vec<Clash>,
+        mut supply: Option<&mut VarSupply>,
+    ) {
+        for (k, t1) in &r1.fields {
+            if let Some(t2) = r2.fields.get(k) {
+                self.unify_sub(t1, t2, at, origins, clashes, supply.as_deref_mut());
+            }
+        }
+        // the fields only in r1, and only in r2
+        let only = |r: &RecordType, other: &RecordType| {
+            let mut res = RecordType::new();
+            for (k, t) in &r.fields {
+                if !other.fields.contains_key(k) {
+                    res.fields.insert(k.clone(), t.clone());
+                }
+            }
+            res
+        };
+        let (mut only1, mut only2) = (only(r1, r2), only(r2, r1));
+        match (&r1.rest, &r2.rest) {
+            (Some(rest1), None) => {
+                self.unify_sub(rest1, &Term::from(only2), at, origins, clashes, supply);
+            }
+            (None, Some(rest2)) => {
+                self.unify_sub(&Term::from(only1), rest2, at, origins, clashes, supply);
+            }
+            (Some(rest1), Some(rest2)) => {
+                let same = self.find(rest1).clone() == *self.find(rest2);
+                if only1.fields.is_empty() && only2.fields.is_empty() {
+                    self.unify_sub(rest1, rest2, at, origins, clashes, supply);
+                } else if !same && only1.fields.is_empty() {
+                    only2.rest = Some(rest2.clone());
+                    self.unify_sub(rest1, &Term::from(only2), at, origins, clashes, supply);
+                } else if !same && only2.fields.is_empty() {
+                    only1.rest = Some(rest1.clone());
+                    self.unify_sub(&Term::from(only1), rest2, at, origins, clashes, supply);
+                } else if let (false, Some(supply)) = (same, supply) {
+                    let rest = supply.fresh();
+                    only1.rest = Some(Box::new(rest.clone()));
+                    only2.rest = Some(Box::new(rest));
+                    self.unify_sub(
+                        rest1,
+                        &Term::from(only2),
+                        at,
+                        origins,
+                        clashes,
+                        Some(&mut *supply),
+                    );
+                    self.unify_sub(
+                        &Term::from(only1),
+                        rest2,
+                        at,
+                        origins,
+                        clashes,
+                        Some(supply),
+                    );
                 } else {
-                    let par = par.clone();
-                    let y = self.find(&par).clone();
-                    self.0.insert(key.clone(), y);
-                    self.0.get(key).unwrap()
+                    // {F1 | ρ} = {F2 | ρ} with F1 != F2 has no finite solution,
+                    // and union has no VarSupply for a fresh row
+                    for k in only1.fields.keys().chain(only2.fields.keys()) {
+                        clashes.push(Clash {
+                            left: Term::from(r1.clone()),
+                            right: Term::from(r2.clone()),
+                            left_origin: origins.0.clone(),
+                            right_origin: origins.1.clone(),
+                            field: Some(k.clone()),
+                        });
+                    }
                 }
             }
-            None => {
-                self.0.insert(key.clone(), key.clone());
-                self.0.get(key).unwrap()
+            (None, None) => {}
+        }
+    }
+
+    /// unify the subterms of two constructors
+    /// a subterm without origin of its own keeps the origin of its constructor
+    fn unify_sub(
+        &mut self,
+        t1: &Term,
+        t2: &Term,
+        at: Option<&AstNode>,
+        origins: &Origins,
+        clashes: &mut Vec<Clash>,
+        supply: Option<&mut VarSupply>,
+    ) {
+        let o1 = self.var_origin(t1).or_else(|| origins.0.clone());
+        let o2 = self.var_origin(t2).or_else(|| origins.1.clone());
+        self.unify(t1, t2, at, (o1, o2), clashes, supply);
+    }
+
+    fn var_origin(&mut self, t: &Term) -> Option<AstNode> {
+        match t {
+            Term::Var(_) => {
+                self.find(t);
+                self.origins.get(t).cloned()
             }
+            _ => None,
+        }
+    }
+
+    /// var: the root of a class without constructor
+    fn bind(&mut self, var: Term, t: Term, at: Option<&AstNode>) {
+        if let (Some(at), Term::Cons(_) | Term::Mu(_)) = (at, &t) {
+            self.origins
+                .entry(var.clone())
+                .or_insert_with(|| at.clone());
         }
+        self.parent.insert(var, t);
+    }
+
+    fn find(&mut self, key: &Term) -> &Term {
+        let root = match self.parent.get(key) {
+            Some(par) if par != key => {
+                let par = par.clone();
+                let y = self.find(&par).clone();
+                // path compression must not lose where the constructor comes from
+                if !self.origins.contains_key(key) {
+                    if let Some(o) = self.origins.get(&par).cloned() {
+                        self.origins.insert(key.clone(), o);
+                    }
+                }
+                y
+            }
+            _ => key.clone(),
+        };
+        self.parent.insert(key.clone(), root);
+        self.parent.get(key).unwrap()
     }
 
     pub fn solution(mut self) -> HashMap<Term, Term> {
         let mut h = HashMap::new();
-        let keys: Vec<Term> = self.0.keys().cloned().collect();
+        let keys: Vec<Term> = self.parent.keys().cloned().collect();
         for k in keys {
             let v = self.find(&k).clone();
             h.insert(k, v);
@@ -90,3 +322,25 @@ impl UnionFindSolver {
         h
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::term::*;
+    use crate::union_find::UnionFindSolver;
+
+    #[test]
+    fn test_union_open_records() {
+        let mut supply = VarSupply::new();
+        let int = Term::Cons(Cons::IntType);
+        let r1 = Term::from(RecordType::open("f", int.clone(), supply.fresh()));
+        let r2 = Term::from(RecordType::open("g", int, supply.fresh()));
+        // without a VarSupply there is no common row for the extra fields
+        let mut fields: Vec<String> = UnionFindSolver::new()
+            .union(&r1, &r2)
+            .into_iter()
+            .filter_map(|clash| clash.field)
+            .collect();
+        fields.sort();
+        assert_eq!(fields, vec!["f", "g"]);
+    }
+}
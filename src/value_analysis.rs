@@ -0,0 +1,322 @@
+use crate::ast_parser::*;
+use crate::available_expressions::Writes;
+use crate::cfg::{address_taken, def, Cfg, CfgNode};
+use crate::dataflow::{per_function, Dataflow, DataflowResult, Direction};
+use crate::dfs::Dfs;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+/// abstract values of TIP integers
+pub trait ValueLattice: Clone + PartialEq {
+    fn top() -> Self;
+
+    fn bot() -> Self;
+
+    fn join(&self, other: &Self) -> Self;
+
+    fn meet(&self, other: &Self) -> Self;
+
+    /// abstraction of a literal
+    fn constant(n: i32) -> Self;
+
+    /// abstraction of `left op right`
+    fn binary(op: &Op, left: &Self, right: &Self) -> Self;
+
+    /// values greater than some value of `v`
+    fn greater_than(v: &Self) -> Self;
+
+    /// values less than some value of `v`
+    fn less_than(v: &Self) -> Self;
+
+    /// values of self, except v if v is a single value
+    fn remove(&self, _v: &Self) -> Self {
+        self.clone()
+    }
+
+    /// lattices with finite height don't need to widen
+    fn widen(&self, new: &Self, _widening: &Widening) -> Self {
+        self.join(new)
+    }
+}
+
+/// how loop heads are widened
+#[derive(Debug, Clone, Default)]
+pub struct Widening {
+    /// an unstable bound jumps to the closest threshold, or to infinity
+    pub thresholds: BTreeSet<i32>,
+}
+
+/// collect every AstNode::Number
+struct NumberCollector {
+    numbers: BTreeSet<i32>,
+}
+
+impl Dfs for NumberCollector {
+    type ResultType = BTreeSet<i32>;
+
+    fn new(_: &AstNode) -> Self {
+        Self {
+            numbers: BTreeSet::new(),
+        }
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        if let AstNodeKind::Number(n) = node.kind {
+            self.numbers.insert(n);
+        }
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        self.numbers
+    }
+}
+
+impl Widening {
+    /// widen unstable bounds to infinity directly
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// use the integer constants of the program as thresholds
+    pub fn from_program(program: &AstNode) -> Self {
+        Self {
+            thresholds: NumberCollector::work(program),
+        }
+    }
+}
+
+/// a fact maps every param and var (declaration) to an abstract value
+/// an unreachable program point maps every variable to bot
+pub type ValueState<L> = HashMap<AstNode, L>;
+
+/// forward analysis of integer values of params and vars
+/// pointers, records and functions are top
+pub struct ValueAnalysis<'a, L> {
+    // generate from DeclarationAnalysis
+    decl: &'a HashMap<AstNode, AstNode>,
+    /// params and vars
+    vars: Vec<AstNode>,
+    /// `&x` is taken, `*p = e` or a call may overwrite x
+    escaped: HashSet<AstNode>,
+    widening: Widening,
+    lattice: std::marker::PhantomData<L>,
+}
+
+impl<'a, L: ValueLattice> ValueAnalysis<'a, L> {
+    pub fn new(cfg: &Cfg, decl: &'a HashMap<AstNode, AstNode>) -> Self {
+        let vars = if let AstNodeKind::Function(Function {
+            ref params,
+            ref vars,
+            ..
+        }) = cfg.function.kind
+        {
+            params.iter().chain(vars.iter()).cloned().collect()
+        } else {
+            unreachable!();
+        };
+        Self {
+            decl,
+            vars,
+            escaped: address_taken(&cfg.function, decl),
+            widening: Widening::new(),
+            lattice: std::marker::PhantomData,
+        }
+    }
+
+    pub fn with_widening(mut self, widening: Widening) -> Self {
+        self.widening = widening;
+        self
+    }
+
+    /// abstract value of an expression
+    pub fn eval(&self, expr: &AstNode, state: &ValueState<L>) -> L {
+        match expr.kind {
+            AstNodeKind::Number(n) => L::constant(n),
+            AstNodeKind::Id(_) => match self.decl.get(expr) {
+                Some(x) => state.get(x).cloned().unwrap_or_else(L::top),
+                None => L::top(),
+            },
+            AstNodeKind::Expression(BinaryOp {
+                ref op,
+                ref left,
+                ref right,
+            }) => L::binary(op, &self.eval(left, state), &self.eval(right, state)),
+            _ => L::top(),
+        }
+    }
+
+    fn is_unreachable(&self, state: &ValueState<L>) -> bool {
+        !self.vars.is_empty() && state.values().all(|v| v == &L::bot())
+    }
+
+    fn unreachable(&self) -> ValueState<L> {
+        self.vars.iter().map(|x| (x.clone(), L::bot())).collect()
+    }
+
+    /// narrow `state` with `guard` being true (branch) or false (!branch)
+    /// bot if the branch is infeasible
+    pub fn refine(&self, guard: &AstNode, branch: bool, state: &ValueState<L>) -> ValueState<L> {
+        if self.is_unreachable(state) {
+            return state.clone();
+        }
+        let mut res = state.clone();
+        // x := x meet v
+        let mut narrow = |id: &AstNode, v: L| {
+            if let Some(x) = self.decl.get(id).filter(|x| res.contains_key(x)) {
+                let old = res[x].clone();
+                res.insert(x.clone(), old.meet(&v));
+            }
+        };
+        let zero = L::constant(0);
+        match guard.kind {
+            AstNodeKind::Expression(BinaryOp {
+                op: Op::Gt,
+                ref left,
+                ref right,
+            }) => {
+                let (l, r) = (self.eval(left, state), self.eval(right, state));
+                if branch {
+                    // left > right
+                    narrow(left, L::greater_than(&r));
+                    narrow(right, L::less_than(&l));
+                } else {
+                    // left <= right, without computing right + 1 which may overflow
+                    narrow(left, L::less_than(&r).join(&r));
+                    narrow(right, L::greater_than(&l).join(&l));
+                }
+            }
+            AstNodeKind::Expression(BinaryOp {
+                op: Op::Equal,
+                ref left,
+                ref right,
+            }) => {
+                let (l, r) = (self.eval(left, state), self.eval(right, state));
+                if branch {
+                    narrow(left, r);
+                    narrow(right, l);
+                } else {
+                    narrow(left, l.remove(&r));
+                    narrow(right, r.remove(&l));
+                }
+            }
+            AstNodeKind::Id(_) => {
+                if branch {
+                    narrow(guard, self.eval(guard, state).remove(&zero));
+                } else {
+                    narrow(guard, zero.clone());
+                }
+            }
+            _ => {}
+        }
+        let v = self.eval(guard, &res);
+        let feasible = if branch {
+            v.remove(&zero) != L::bot()
+        } else {
+            v.meet(&zero) != L::bot()
+        };
+        if !feasible || res.values().any(|v| v == &L::bot()) {
+            return self.unreachable();
+        }
+        res
+    }
+}
+
+impl<'a, L: ValueLattice> Dataflow for ValueAnalysis<'a, L> {
+    type Fact = ValueState<L>;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    /// params are unknown, vars are undefined
+    fn boundary(&self, _: &Cfg) -> Self::Fact {
+        self.vars.iter().map(|x| (x.clone(), L::top())).collect()
+    }
+
+    fn init(&self, _: &Cfg) -> Self::Fact {
+        self.unreachable()
+    }
+
+    fn join(&self, a: &Self::Fact, b: &Self::Fact) -> Self::Fact {
+        a.iter().map(|(k, v)| (k.clone(), v.join(&b[k]))).collect()
+    }
+
+    fn transfer(&self, node: &CfgNode, fact: &Self::Fact) -> Self::Fact {
+        if self.is_unreachable(fact) {
+            return fact.clone();
+        }
+        let mut res = fact.clone();
+        if Writes::new(node, &HashSet::new()).writes_heap() {
+            for x in &self.escaped {
+                res.insert(x.clone(), L::top());
+            }
+        }
+        if let Some(x) = def(node, self.decl) {
+            if let CfgNode::Stmt(AstNode {
+                kind: AstNodeKind::Assign(Assign { ref right, .. }),
+                ..
+            }) = node
+            {
+                res.insert(x, self.eval(right, fact));
+            }
+        }
+        res
+    }
+
+    /// the guard of if/while is true in the first branch, false in the other
+    fn transfer_edge(&self, from: &CfgNode, branch: Option<bool>, fact: &Self::Fact) -> Self::Fact {
+        match (from, branch) {
+            (CfgNode::Guard(stmt), Some(branch)) => match stmt.kind {
+                AstNodeKind::If(If { ref guard, .. })
+                | AstNodeKind::While(While { ref guard, .. }) => self.refine(guard, branch, fact),
+                _ => unreachable!(),
+            },
+            _ => fact.clone(),
+        }
+    }
+
+    /// widen at the head of every while loop
+    fn widen(&self, node: &CfgNode, old: &Self::Fact, new: &Self::Fact) -> Self::Fact {
+        if let CfgNode::Guard(AstNode {
+            kind: AstNodeKind::While(_),
+            ..
+        }) = node
+        {
+            old.iter()
+                .map(|(k, v)| (k.clone(), v.widen(&new[k], &self.widening)))
+                .collect()
+        } else {
+            new.clone()
+        }
+    }
+}
+
+/// one line for each statement of a function:
+/// `line:col: x = v, y = v` with the values before the statement
+pub fn report<L: ValueLattice + fmt::Display>(
+    cfg: &Cfg,
+    result: &DataflowResult<ValueState<L>>,
+) -> Vec<String> {
+    let mut res = vec![];
+    for (i, node) in cfg.nodes.iter().enumerate() {
+        if let Some(stmt) = node.ast() {
+            let mut values: Vec<String> = result.before[i]
+                .iter()
+                .map(|(k, v)| format!("{} = {}", k, v))
+                .collect();
+            values.sort();
+            res.push(format!("{}:{}: {}", stmt.line, stmt.col, values.join(", ")));
+        }
+    }
+    res
+}
+
+/// values of every function, the key is the function name
+pub fn value_analysis<L: ValueLattice>(
+    program: &AstNode,
+) -> HashMap<String, (Cfg, DataflowResult<ValueState<L>>)> {
+    per_function(program, |cfg, decl| {
+        ValueAnalysis::<L>::new(cfg, decl).solve(cfg)
+    })
+}
@@ -0,0 +1,159 @@
+use crate::ast_parser::*;
+use crate::available_expressions::{escaped_names, subexpressions, Writes};
+use crate::cfg::{Cfg, CfgNode};
+use crate::dataflow::{per_function, Dataflow, DataflowResult, Direction};
+use crate::dfs::Dfs;
+use std::collections::{HashMap, HashSet};
+
+/// backward must analysis
+/// a fact is the set of expressions (without position) which will be evaluated
+/// on every path before any of their operands change
+pub struct VeryBusyExpressions {
+    /// every expression of the function
+    universe: HashSet<AstNode>,
+    escaped: HashSet<String>,
+}
+
+impl VeryBusyExpressions {
+    pub fn new(cfg: &Cfg, decl: &HashMap<AstNode, AstNode>) -> Self {
+        Self {
+            universe: cfg.nodes.iter().flat_map(subexpressions).collect(),
+            escaped: escaped_names(cfg, decl),
+        }
+    }
+}
+
+impl Dataflow for VeryBusyExpressions {
+    type Fact = HashSet<AstNode>;
+
+    fn direction(&self) -> Direction {
+        Direction::Backward
+    }
+
+    fn boundary(&self, _: &Cfg) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn init(&self, _: &Cfg) -> Self::Fact {
+        self.universe.clone()
+    }
+
+    fn join(&self, a: &Self::Fact, b: &Self::Fact) -> Self::Fact {
+        a.intersection(b).cloned().collect()
+    }
+
+    fn transfer(&self, node: &CfgNode, fact: &Self::Fact) -> Self::Fact {
+        let writes = Writes::new(node, &self.escaped);
+        fact.iter()
+            .filter(|e| !writes.kills(e))
+            .cloned()
+            .chain(subexpressions(node))
+            .collect()
+    }
+}
+
+/// very busy expressions of every function, the key is the function name
+pub fn very_busy_expressions(
+    program: &AstNode,
+) -> HashMap<String, (Cfg, DataflowResult<HashSet<AstNode>>)> {
+    per_function(program, |cfg, decl| {
+        VeryBusyExpressions::new(cfg, decl).solve(cfg)
+    })
+}
+
+/// collect every AstNode inside a statement, the statement included
+struct Statements(HashSet<AstNode>);
+
+impl Dfs for Statements {
+    type ResultType = HashSet<AstNode>;
+
+    fn new(_: &AstNode) -> Self {
+        Statements(HashSet::new())
+    }
+
+    fn visit(&mut self, node: &AstNode) -> bool {
+        self.0.insert(node.clone());
+        true
+    }
+
+    fn finish(self) -> Self::ResultType {
+        self.0
+    }
+}
+
+/// expressions which are evaluated in both branches of an AstNode::If
+/// and can be hoisted before it
+/// an expression evaluated by every path after the if anyway is left out
+/// the key is the AstNode::If
+pub fn hoistable_expressions(program: &AstNode) -> HashMap<AstNode, HashSet<AstNode>> {
+    let mut res = HashMap::new();
+    for (_, (cfg, busy)) in very_busy_expressions(program) {
+        for (i, node) in cfg.nodes.iter().enumerate() {
+            let stmt = match node {
+                CfgNode::Guard(
+                    stmt @ AstNode {
+                        kind: AstNodeKind::If(_),
+                        ..
+                    },
+                ) => stmt,
+                _ => continue,
+            };
+            // the first node of each branch, an empty branch leads to the join
+            let (then, otherwise) = match cfg.succ[i][..] {
+                [t, f] => (t, f),
+                // both branches are empty
+                _ => continue,
+            };
+            // where both branches meet: the first node out of the if
+            let inside = Statements::work(stmt);
+            let within = |j: usize| cfg.nodes[j].ast().is_some_and(|a| inside.contains(a));
+            let join = (0..cfg.len())
+                .filter(|&j| within(j))
+                .flat_map(|j| cfg.succ[j].iter().cloned())
+                .find(|&j| !within(j))
+                .unwrap();
+            // the guard evaluates its own expressions before branching
+            let guard = subexpressions(node);
+            let exprs: HashSet<AstNode> = busy.before[then]
+                .intersection(&busy.before[otherwise])
+                .filter(|e| !guard.contains(e) && !busy.before[join].contains(e))
+                .cloned()
+                .collect();
+            if !exprs.is_empty() {
+                res.insert(stmt.clone(), exprs);
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_parser::{parse, AstNode};
+    use crate::available_expressions::subexpressions;
+    use crate::very_busy_expressions::{hoistable_expressions, very_busy_expressions};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_very_busy_expressions() {
+        let source = "main() { var a, b, x, y; a = input; b = input;
+             if (a > 0) { x = a * b; y = b + 1; x = a - b; } else { y = b + 1; x = a * b; }
+             a = a - b; return x + y; }";
+        let program = parse(source);
+        let res = very_busy_expressions(&program);
+        let (cfg, busy) = &res["main"];
+        // before the guard: a > 0, a * b, b + 1, a - b
+        assert_eq!(busy.before[cfg.find(source, "if (a > 0)")].len(), 4);
+        // a * b is not busy before a = input
+        assert!(busy.before[cfg.find(source, "a = input;")].is_empty());
+
+        // a - b is evaluated after the if anyway
+        let hoistable = hoistable_expressions(&program);
+        assert_eq!(hoistable.len(), 1);
+        let expected: HashSet<AstNode> = ["x = a * b;", "y = b + 1;"]
+            .iter()
+            .flat_map(|s| subexpressions(&cfg.nodes[cfg.find(source, s)]))
+            .collect();
+        assert_eq!(hoistable.values().next().unwrap(), &expected);
+    }
+}